@@ -1,16 +1,161 @@
+#![allow(clippy::needless_return)]
+
+use std::cmp::max;
+use std::cmp::min;
 use std::env;
+use std::fmt;
 use std::fs;
+use std::process;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::Instant;
+
+// Marks a faulty cell. In bases above 33 `x` is itself a digit, so banks
+// written in those bases cannot mark faulty cells.
+const DISALLOWED_MARKER: char = 'x';
+
+const MAX_ENABLED: u64 = 12;
 
 #[derive(Debug, PartialEq)]
 struct Bank {
     batteries: Vec<u64>,
-    max_enabled: u64
+    max_enabled: u64,
+    // Minimum number of unused cells between two enabled batteries.
+    min_gap: usize,
+    // Sorted indices of faulty cells that may never be enabled.
+    disallowed: Vec<usize>,
+    radix: u32
+}
+
+impl Default for Bank {
+    fn default() -> Self {
+        Bank {
+            batteries: Vec::new(),
+            max_enabled: 0,
+            min_gap: 0,
+            disallowed: Vec::new(),
+            radix: 10
+        }
+    }
 }
 
-fn digits_to_value(digits: &[u64]) -> u64 {
+#[derive(Debug, PartialEq)]
+enum LineError {
+    Empty,
+    InvalidDigit { column: usize, found: char, radix: u32 },
+    TooShort { length: usize, max_enabled: u64 },
+    NoValidSelection { max_enabled: u64 }
+}
+
+impl fmt::Display for LineError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LineError::Empty => write!(f, "empty bank"),
+            LineError::InvalidDigit { column, found, radix } => {
+                write!(f, "column {}: '{}' is not a base {} digit", column, found, radix)
+            },
+            LineError::TooShort { length, max_enabled } => {
+                write!(f, "bank has {} batteries but {} must be enabled", length, max_enabled)
+            },
+            LineError::NoValidSelection { max_enabled } => {
+                write!(f, "no valid selection of {} batteries", max_enabled)
+            }
+        }
+    }
+}
+
+// What to do with a line that has no joltage of its own.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum OnError {
+    Skip,
+    Fail,
+    // Enable as many batteries as the bank allows. Lines that fail to parse
+    // are still skipped.
+    EnableAll
+}
+
+#[derive(Debug, PartialEq)]
+struct Evaluation {
+    joltage: u64,
+    error: Option<LineError>
+}
+
+// One bank's row in the CSV report.
+#[derive(Debug, PartialEq)]
+struct ReportRow {
+    line: usize,
+    length: usize,
+    chosen: Vec<usize>,
+    joltage: u64,
+    running_total: u128,
+    error: Option<LineError>
+}
+
+impl ReportRow {
+    const HEADER: &'static str = "line,length,enabled,chosen,joltage,running_total,error";
+}
+
+impl fmt::Display for ReportRow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let chosen: Vec<String> = self.chosen.iter().map(|index| index.to_string()).collect();
+        let error = match &self.error {
+            Some(error) => format!("\"{}\"", error.to_string().replace('"', "\"\"")),
+            None => String::new()
+        };
+
+        write!(
+            f,
+            "{},{},{},{},{},{},{}",
+            self.line,
+            self.length,
+            self.chosen.len(),
+            chosen.join(" "),
+            self.joltage,
+            self.running_total,
+            error
+        )
+    }
+}
+
+// Totals for a run of consecutive lines. Error line numbers are relative to
+// the first line of the run.
+#[derive(Debug, Default, PartialEq)]
+struct ChunkTotal {
+    lines: usize,
+    joltage: u128,
+    errors: Vec<(usize, LineError)>
+}
+
+#[derive(Debug, PartialEq)]
+struct Options {
+    file_name: String,
+    min_gap: usize,
+    radix: u32,
+    on_error: OnError,
+    // Worker threads for multi-threaded mode, where 0 means one per core.
+    threads: Option<usize>,
+    // Emit a CSV row per bank instead of the total. Always single threaded.
+    csv: bool
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            file_name: String::new(),
+            min_gap: 0,
+            radix: 10,
+            on_error: OnError::Skip,
+            threads: None,
+            csv: false
+        }
+    }
+}
+
+fn digits_to_value(digits: &[u64], radix: u32) -> u64 {
     let mut value = 0;
     for (index, digit) in digits.iter().rev().enumerate() {
-        value += digit * ((10 as u64).pow(index as u32));
+        value += digit * (radix as u64).pow(index as u32);
     }
     return value;
 }
@@ -36,54 +181,375 @@ fn shift(digits: &[u64], value: u64) -> Vec<u64> {
 }
 
 impl Bank {
-    fn max_joltage(&self) -> u64 {
+    fn is_disallowed(&self, index: usize) -> bool {
+        self.disallowed.binary_search(&index).is_ok()
+    }
+
+    fn is_unconstrained(&self) -> bool {
+        self.min_gap == 0
+            && self.disallowed.is_empty()
+            && self.max_enabled > 0
+            && self.batteries.len() >= self.max_enabled as usize
+    }
+
+    // selectable[i] is the most batteries that can be enabled using only
+    // cells i.., so selectable[0] tells us whether a selection exists at all.
+    fn selectable_from(&self) -> Vec<usize> {
+        let len = self.batteries.len();
+        let mut selectable = vec![0; len + 1];
+
+        for index in (0..len).rev() {
+            let take = if self.is_disallowed(index) {
+                0
+            } else {
+                1 + selectable.get(index + self.min_gap + 1).copied().unwrap_or(0)
+            };
+            selectable[index] = max(selectable[index + 1], take);
+        }
+
+        return selectable;
+    }
+
+    // Picks the highest battery that still leaves room for the rest, one
+    // position at a time. Taking the earliest of equal batteries never hurts
+    // since it leaves the most cells for later picks.
+    fn selection(&self) -> Option<Vec<usize>> {
+        let wanted = self.max_enabled as usize;
+        let selectable = self.selectable_from();
+        if selectable[0] < wanted {
+            return None;
+        }
+
+        let mut chosen = Vec::with_capacity(wanted);
+        let mut start = 0;
+        for remaining in (0..wanted).rev() {
+            let mut best: Option<usize> = None;
+            for index in start..self.batteries.len() {
+                let after = selectable.get(index + self.min_gap + 1).copied().unwrap_or(0);
+                if after < remaining {
+                    break;
+                }
+                if self.is_disallowed(index) {
+                    continue;
+                }
+                if best.is_none_or(|best| self.batteries[index] > self.batteries[best]) {
+                    best = Some(index);
+                }
+            }
+
+            let index = best?;
+            chosen.push(index);
+            start = index + self.min_gap + 1;
+        }
+
+        return Some(chosen);
+    }
+
+    fn has_selection(&self) -> bool {
+        self.is_unconstrained() || self.selectable_from()[0] >= self.max_enabled as usize
+    }
+
+    fn value_of(&self, chosen: &[usize]) -> u64 {
+        let digits: Vec<u64> = chosen
+            .iter()
+            .map(|index| self.batteries[*index])
+            .collect();
+        return digits_to_value(&digits[..], self.radix);
+    }
+
+    fn max_joltage(&self) -> Option<u64> {
+        if !self.is_unconstrained() {
+            return Some(self.value_of(&self.selection()?));
+        }
+
         let max_batteries = self.max_enabled as usize;
         let mut digits = Vec::from(&self.batteries[0..max_batteries]);
         let last_index = digits.len() - 1;
 
-        let mut max = digits_to_value(&digits[..]);
+        let mut max = digits_to_value(&digits[..], self.radix);
 
         for current in self.batteries.iter().skip(max_batteries) {
             let shift = shift(&digits[..], *current);
-            let shifted_value = digits_to_value(&shift[..]);
+            let shifted_value = digits_to_value(&shift[..], self.radix);
 
             if shifted_value > max {
                 digits = shift;
                 max = shifted_value;
             } else if *current > digits[last_index] {
                 digits[last_index] = *current;
-                max = digits_to_value(&digits[..]);
+                max = digits_to_value(&digits[..], self.radix);
             }
         }
         
-        return max;
+        return Some(max);
+    }
+
+    fn enable_all(&mut self) {
+        self.max_enabled = min(self.max_enabled, self.selectable_from()[0] as u64);
     }
 
-    fn parse_line(line: &str, max_enabled: u64) -> Bank {
+    fn parse_line(line: &str, max_enabled: u64, radix: u32) -> Result<Bank, LineError> {
+        if line.is_empty() {
+            return Err(LineError::Empty);
+        }
+
         let mut values = Vec::with_capacity(64);
-        for current in line.chars() {
-            values.push(current.to_digit(10).unwrap() as u64);
+        let mut disallowed = Vec::new();
+        for (index, current) in line.chars().enumerate() {
+            if let Some(digit) = current.to_digit(radix) {
+                values.push(digit as u64);
+            } else if current == DISALLOWED_MARKER {
+                disallowed.push(index);
+                values.push(0);
+            } else {
+                return Err(LineError::InvalidDigit {
+                    column: index + 1,
+                    found: current,
+                    radix
+                });
+            }
         }
 
-        Bank {
+        Ok(Bank {
             batteries: values,
-            max_enabled: max_enabled
+            max_enabled,
+            min_gap: 0,
+            disallowed,
+            radix
+        })
+    }
+}
+
+// Parses a line into the bank to evaluate, applying the error policy. There is
+// no bank when the line contributes nothing to the total.
+fn prepare_line(line: &str, options: &Options) -> (Option<Bank>, Option<LineError>) {
+    let mut bank = match Bank::parse_line(line, MAX_ENABLED, options.radix) {
+        Ok(bank) => bank,
+        Err(error) => return (None, Some(error))
+    };
+    bank.min_gap = options.min_gap;
+
+    if bank.has_selection() {
+        return (Some(bank), None);
+    }
+
+    let error = if bank.batteries.len() < bank.max_enabled as usize {
+        LineError::TooShort {
+            length: bank.batteries.len(),
+            max_enabled: bank.max_enabled
+        }
+    } else {
+        LineError::NoValidSelection { max_enabled: bank.max_enabled }
+    };
+
+    if options.on_error != OnError::EnableAll {
+        return (None, Some(error));
+    }
+
+    bank.enable_all();
+    return (Some(bank), Some(error));
+}
+
+fn evaluate_line(line: &str, options: &Options) -> Evaluation {
+    let (bank, error) = prepare_line(line, options);
+    let joltage = bank.and_then(|bank| bank.max_joltage()).unwrap_or(0);
+
+    return Evaluation { joltage, error };
+}
+
+fn report_line(index: usize, line: &str, options: &Options, running_total: u128) -> ReportRow {
+    let (bank, error) = prepare_line(line, options);
+    let (length, chosen, joltage) = match bank {
+        Some(bank) => {
+            let chosen = bank.selection().unwrap_or_default();
+            let joltage = bank.value_of(&chosen);
+            (bank.batteries.len(), chosen, joltage)
+        },
+        None => (line.chars().count(), Vec::new(), 0)
+    };
+
+    return ReportRow {
+        line: index + 1,
+        length,
+        chosen,
+        joltage,
+        running_total: running_total + joltage as u128,
+        error
+    };
+}
+
+fn parse_args(args: &[String]) -> Options {
+    let mut options = Options::default();
+
+    let mut args = args.iter().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--min-gap" => {
+                options.min_gap = args.next()
+                    .and_then(|value| value.parse().ok())
+                    .expect("--min-gap expects a number");
+            },
+            "--radix" => {
+                options.radix = args.next()
+                    .and_then(|value| value.parse().ok())
+                    .expect("--radix expects a number");
+                assert!((2..=36).contains(&options.radix), "--radix must be between 2 and 36");
+            },
+            "--on-error" => {
+                options.on_error = match args.next().map(|value| value.as_str()) {
+                    Some("skip") => OnError::Skip,
+                    Some("fail") => OnError::Fail,
+                    Some("enable-all") => OnError::EnableAll,
+                    _ => panic!("--on-error expects skip, fail or enable-all")
+                };
+            },
+            "--threads" => {
+                options.threads = Some(args.next()
+                    .and_then(|value| value.parse().ok())
+                    .expect("--threads expects a number"));
+            },
+            "--csv" => options.csv = true,
+            _ => options.file_name = arg.clone()
         }
     }
+
+    return options;
+}
+
+// With `--on-error fail` a chunk stops at its first error and records its
+// index in `first_failure`, and gives up as soon as an earlier chunk has
+// failed. Later chunks can't hold the earliest error, so they are left alone.
+fn evaluate_chunk(chunk: &str, options: &Options, index: usize, first_failure: &AtomicUsize) -> ChunkTotal {
+    let mut total = ChunkTotal::default();
+
+    for line in chunk.lines() {
+        if first_failure.load(Ordering::Relaxed) < index {
+            break;
+        }
+
+        let evaluation = evaluate_line(line, options);
+        total.joltage += evaluation.joltage as u128;
+        total.lines += 1;
+        if let Some(error) = evaluation.error {
+            total.errors.push((total.lines - 1, error));
+            if options.on_error == OnError::Fail {
+                first_failure.fetch_min(index, Ordering::Relaxed);
+                break;
+            }
+        }
+    }
+
+    return total;
+}
+
+// Splits input into at most `count` chunks, each ending on a line boundary.
+fn split_chunks(input: &str, count: usize) -> Vec<&str> {
+    let target = input.len().div_ceil(count.max(1));
+    let mut chunks = Vec::with_capacity(count);
+    let mut rest = input;
+
+    while !rest.is_empty() {
+        let end = match rest.as_bytes().iter().skip(target).position(|byte| *byte == b'\n') {
+            Some(offset) => target + offset + 1,
+            None => rest.len()
+        };
+        let (chunk, remaining) = rest.split_at(end);
+        chunks.push(chunk);
+        rest = remaining;
+    }
+
+    return chunks;
+}
+
+// Evaluates chunks on their own threads and combines them in input order, so
+// the total and the order of errors do not depend on scheduling. With
+// `--on-error fail` only the earliest error is kept.
+fn evaluate_parallel(input: &str, options: &Options, threads: usize) -> ChunkTotal {
+    let chunks = split_chunks(input, threads);
+    let first_failure = AtomicUsize::new(usize::MAX);
+    let totals: Vec<ChunkTotal> = thread::scope(|scope| {
+        let workers: Vec<_> = chunks
+            .iter()
+            .enumerate()
+            .map(|(index, chunk)| {
+                let first_failure = &first_failure;
+                scope.spawn(move || evaluate_chunk(chunk, options, index, first_failure))
+            })
+            .collect();
+
+        workers.into_iter().map(|worker| worker.join().unwrap()).collect()
+    });
+
+    let mut combined = ChunkTotal::default();
+    for total in totals {
+        for (line, error) in total.errors {
+            combined.errors.push((combined.lines + line, error));
+        }
+        combined.joltage += total.joltage;
+        combined.lines += total.lines;
+        if options.on_error == OnError::Fail && !combined.errors.is_empty() {
+            break;
+        }
+    }
+
+    return combined;
 }
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    let file_name = &args[1];
+    let options = parse_args(&args);
+
+    let input = fs::read_to_string(&options.file_name).unwrap();
 
-    let total_joltage = fs::read_to_string(file_name)
-        .unwrap()
-        .lines()
-        .fold(0, |acc, line| {
-            acc + Bank::parse_line(line, 12).max_joltage()
-        });
+    if options.csv {
+        println!("{}", ReportRow::HEADER);
+
+        let mut running_total = 0;
+        for (index, line) in input.lines().enumerate() {
+            let row = report_line(index, line, &options, running_total);
+            if let Some(error) = &row.error {
+                eprintln!("Line {}: {}", row.line, error);
+                if options.on_error == OnError::Fail {
+                    process::exit(1);
+                }
+            }
+
+            running_total = row.running_total;
+            println!("{}", row);
+        }
+        return;
+    }
 
-    println!("Total joltage: {}", total_joltage);
+    let threads = match options.threads {
+        Some(0) => thread::available_parallelism().map_or(1, |count| count.get()),
+        Some(threads) => threads,
+        None => 1
+    };
+
+    let start = Instant::now();
+    let total = evaluate_parallel(&input, &options, threads);
+    let elapsed = start.elapsed();
+
+    for (line, error) in &total.errors {
+        eprintln!("Line {}: {}", line + 1, error);
+        if options.on_error == OnError::Fail {
+            process::exit(1);
+        }
+    }
+
+    println!("Total joltage: {}", total.joltage);
+
+    if options.threads.is_some() {
+        let seconds = elapsed.as_secs_f64();
+        eprintln!(
+            "Evaluated {} lines ({} bytes) in {:.3}s on {} threads: {:.0} lines/s",
+            total.lines,
+            input.len(),
+            seconds,
+            threads,
+            total.lines as f64 / seconds
+        );
+    }
 }
 
 #[cfg(test)]
@@ -106,65 +572,83 @@ mod tests {
     #[test]
     fn digits_to_value_test() {
         assert_eq!(
-            digits_to_value(&[9,8,7,6,5,4,3,2,1,1,1,1,1,1,1]),
+            digits_to_value(&[9,8,7,6,5,4,3,2,1,1,1,1,1,1,1], 10),
             987654321111111
         );
 
         assert_eq!(
-            digits_to_value(&[9,8]),
+            digits_to_value(&[9,8], 10),
             98
         );
+
+        assert_eq!(
+            digits_to_value(&[15,15], 16),
+            255
+        );
+
+        assert_eq!(
+            digits_to_value(&[1,0,1], 2),
+            5
+        );
     }
 
     #[test]
     fn max_bank_joltage() {
         let mut bank = Bank {
             batteries: vec![9,8,7,6,5,4,3,2,1,1,1,1,1,1,1],
-            max_enabled: 2
+            max_enabled: 2,
+            ..Default::default()
         };
-        assert_eq!(bank.max_joltage(), 98);
+        assert_eq!(bank.max_joltage(), Some(98));
 
         bank = Bank {
             batteries: vec![8,1,1,1,1,1,1,1,1,1,1,1,1,1,9],
-            max_enabled: 2
+            max_enabled: 2,
+            ..Default::default()
         };
-        assert_eq!(bank.max_joltage(), 89);
+        assert_eq!(bank.max_joltage(), Some(89));
 
         bank = Bank {
             batteries: vec![2,3,4,2,3,4,2,3,4,2,3,4,2,7,8],
-            max_enabled: 2
+            max_enabled: 2,
+            ..Default::default()
         };
-        assert_eq!(bank.max_joltage(), 78);
+        assert_eq!(bank.max_joltage(), Some(78));
 
         bank = Bank {
             batteries: vec![8,1,8,1,8,1,9,1,1,1,1,2,1,1,1],
-            max_enabled: 2
+            max_enabled: 2,
+            ..Default::default()
         };
-        assert_eq!(bank.max_joltage(), 92);
+        assert_eq!(bank.max_joltage(), Some(92));
 
         bank = Bank {
             batteries: vec![9,8,7,6,5,4,3,2,1,1,1,1,1,1,1],
-            max_enabled: 12
+            max_enabled: 12,
+            ..Default::default()
         };
-        assert_eq!(bank.max_joltage(), 987654321111);
+        assert_eq!(bank.max_joltage(), Some(987654321111));
 
         bank = Bank {
             batteries: vec![8,1,1,1,1,1,1,1,1,1,1,1,1,1,9],
-            max_enabled: 12
+            max_enabled: 12,
+            ..Default::default()
         };
-        assert_eq!(bank.max_joltage(), 811111111119);
+        assert_eq!(bank.max_joltage(), Some(811111111119));
 
         bank = Bank {
             batteries: vec![2,3,4,2,3,4,2,3,4,2,3,4,2,7,8],
-            max_enabled: 12
+            max_enabled: 12,
+            ..Default::default()
         };
-        assert_eq!(bank.max_joltage(), 434234234278);
+        assert_eq!(bank.max_joltage(), Some(434234234278));
 
         bank = Bank {
             batteries: vec![8,1,8,1,8,1,9,1,1,1,1,2,1,1,1],
-            max_enabled: 12
+            max_enabled: 12,
+            ..Default::default()
         };
-        assert_eq!(bank.max_joltage(), 888911112111);
+        assert_eq!(bank.max_joltage(), Some(888911112111));
     }
 
     #[test]
@@ -175,43 +659,299 @@ mod tests {
         // 818181911112111 - 92
 
         assert_eq!(
-            Bank::parse_line("987654321111111", 2),
-            Bank {
+            Bank::parse_line("987654321111111", 2, 10),
+            Ok(Bank {
                 batteries: vec![9,8,7,6,5,4,3,2,1,1,1,1,1,1,1],
-                max_enabled: 2
-            }
+                max_enabled: 2,
+                ..Default::default()
+            })
         );
 
         assert_eq!(
-            Bank::parse_line("811111111111119", 2),
-            Bank {
+            Bank::parse_line("811111111111119", 2, 10),
+            Ok(Bank {
                 batteries: vec![8,1,1,1,1,1,1,1,1,1,1,1,1,1,9],
-                max_enabled: 2
-            }
+                max_enabled: 2,
+                ..Default::default()
+            })
         );
 
         assert_eq!(
-            Bank::parse_line("234234234234278", 2),
-            Bank {
+            Bank::parse_line("234234234234278", 2, 10),
+            Ok(Bank {
                 batteries: vec![2,3,4,2,3,4,2,3,4,2,3,4,2,7,8],
-                max_enabled: 2
-            }
+                max_enabled: 2,
+                ..Default::default()
+            })
         );
 
         assert_eq!(
-            Bank::parse_line("818181911112111", 2),
-            Bank {
+            Bank::parse_line("818181911112111", 2, 10),
+            Ok(Bank {
                 batteries: vec![8,1,8,1,8,1,9,1,1,1,1,2,1,1,1],
-                max_enabled: 2
-            }
+                max_enabled: 2,
+                ..Default::default()
+            })
         );
 
         assert_eq!(
-            Bank::parse_line("818", 2),
-            Bank {
+            Bank::parse_line("818", 2, 10),
+            Ok(Bank {
                 batteries: vec![8,1,8],
-                max_enabled: 2
+                max_enabled: 2,
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn parse_disallowed_cells() {
+        assert_eq!(
+            Bank::parse_line("8x18x", 2, 10),
+            Ok(Bank {
+                batteries: vec![8,0,1,8,0],
+                max_enabled: 2,
+                min_gap: 0,
+                disallowed: vec![1, 4],
+                radix: 10
+            })
+        );
+    }
+
+    #[test]
+    fn constrained_joltage() {
+        let mut bank = Bank::parse_line("987654321111111", 2, 10).unwrap();
+        bank.min_gap = 1;
+        assert_eq!(bank.max_joltage(), Some(97));
+        assert_eq!(bank.selection(), Some(vec![0, 2]));
+
+        bank = Bank::parse_line("9x87654321111111", 2, 10).unwrap();
+        assert_eq!(bank.max_joltage(), Some(98));
+
+        bank = Bank::parse_line("x8x9x", 2, 10).unwrap();
+        assert_eq!(bank.max_joltage(), Some(89));
+
+        bank = Bank::parse_line("818181911112111", 12, 10).unwrap();
+        assert_eq!(bank.selection(), Some(vec![0, 2, 4, 6, 7, 8, 9, 10, 11, 12, 13, 14]));
+    }
+
+    #[test]
+    fn constrained_matches_brute_force() {
+        let line = "3x14159x26535";
+        for max_enabled in 1..6 {
+            for min_gap in 0..3 {
+                let mut bank = Bank::parse_line(line, max_enabled, 10).unwrap();
+                bank.min_gap = min_gap;
+
+                let len = bank.batteries.len();
+                let mut best: Option<u64> = None;
+                for mask in 0_u32..(1 << len) {
+                    let chosen: Vec<usize> = (0..len).filter(|index| mask & (1 << index) != 0).collect();
+                    if chosen.len() != max_enabled as usize
+                        || chosen.iter().any(|index| bank.is_disallowed(*index))
+                        || chosen.windows(2).any(|pair| pair[1] - pair[0] <= min_gap) {
+                        continue;
+                    }
+                    let digits: Vec<u64> = chosen.iter().map(|index| bank.batteries[*index]).collect();
+                    best = max(best, Some(digits_to_value(&digits[..], 10)));
+                }
+
+                assert_eq!(bank.max_joltage(), best, "{} batteries, gap {}", max_enabled, min_gap);
+            }
+        }
+    }
+
+    #[test]
+    fn no_valid_selection() {
+        let mut bank = Bank::parse_line("98765", 3, 10).unwrap();
+        bank.min_gap = 2;
+        assert_eq!(bank.max_joltage(), None);
+
+        bank = Bank::parse_line("9xx", 2, 10).unwrap();
+        assert_eq!(bank.max_joltage(), None);
+
+        bank = Bank::parse_line("9", 2, 10).unwrap();
+        assert_eq!(bank.max_joltage(), None);
+    }
+
+    #[test]
+    fn parse_min_gap_arg() {
+        let args: Vec<String> = ["day3", "input", "--min-gap", "1"]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect();
+
+        assert_eq!(
+            parse_args(&args),
+            Options {
+                file_name: String::from("input"),
+                min_gap: 1,
+                ..Default::default()
             }
         );
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn parse_radix_bank() {
+        assert_eq!(
+            Bank::parse_line("f0aZ", 2, 36),
+            Ok(Bank {
+                batteries: vec![15,0,10,35],
+                max_enabled: 2,
+                radix: 36,
+                ..Default::default()
+            })
+        );
+
+        let bank = Bank::parse_line("1x0f", 2, 16).unwrap();
+        assert_eq!(bank.disallowed, vec![1]);
+        assert_eq!(bank.batteries, vec![1,0,0,15]);
+    }
+
+    #[test]
+    fn radix_joltage() {
+        let mut bank = Bank::parse_line("8a1b1f", 2, 16).unwrap();
+        assert_eq!(bank.max_joltage(), Some(0xbf));
+
+        bank = Bank::parse_line("8a1b1f", 3, 16).unwrap();
+        assert_eq!(bank.max_joltage(), Some(0xb1f));
+
+        bank = Bank::parse_line("8a1b1f", 3, 16).unwrap();
+        bank.min_gap = 1;
+        assert_eq!(bank.max_joltage(), Some(0xabf));
+
+        bank = Bank::parse_line("0110100", 3, 2).unwrap();
+        assert_eq!(bank.max_joltage(), Some(0b111));
+
+        bank = Bank::parse_line("zy9z", 2, 36).unwrap();
+        assert_eq!(bank.max_joltage(), Some(35 * 36 + 35));
+    }
+
+    #[test]
+    fn parse_malformed_bank() {
+        assert_eq!(Bank::parse_line("", 2, 10), Err(LineError::Empty));
+        assert_eq!(
+            Bank::parse_line("98a7", 2, 10),
+            Err(LineError::InvalidDigit { column: 3, found: 'a', radix: 10 })
+        );
+        assert_eq!(
+            Bank::parse_line("12", 2, 2),
+            Err(LineError::InvalidDigit { column: 2, found: '2', radix: 2 })
+        );
+    }
+
+    #[test]
+    fn evaluate_with_policy() {
+        let mut options = Options::default();
+
+        assert_eq!(
+            evaluate_line("818181911112111", &options),
+            Evaluation { joltage: 888911112111, error: None }
+        );
+        assert_eq!(
+            evaluate_line("98765", &options),
+            Evaluation {
+                joltage: 0,
+                error: Some(LineError::TooShort { length: 5, max_enabled: 12 })
+            }
+        );
+        assert_eq!(
+            evaluate_line("", &options),
+            Evaluation { joltage: 0, error: Some(LineError::Empty) }
+        );
+
+        options.on_error = OnError::EnableAll;
+        assert_eq!(
+            evaluate_line("98765", &options),
+            Evaluation {
+                joltage: 98765,
+                error: Some(LineError::TooShort { length: 5, max_enabled: 12 })
+            }
+        );
+        assert_eq!(
+            evaluate_line("98?65", &options).joltage,
+            0
+        );
+
+        options.min_gap = 1;
+        assert_eq!(
+            evaluate_line("98765", &options),
+            Evaluation {
+                joltage: 975,
+                error: Some(LineError::TooShort { length: 5, max_enabled: 12 })
+            }
+        );
+        assert_eq!(
+            evaluate_line("987654321111111111111", &options),
+            Evaluation {
+                joltage: 97531111111,
+                error: Some(LineError::NoValidSelection { max_enabled: 12 })
+            }
+        );
+    }
+
+    #[test]
+    fn split_input_chunks() {
+        let input = "111\n22\n3333\n4\n";
+        assert_eq!(split_chunks(input, 1), vec![input]);
+        assert_eq!(split_chunks(input, 2), vec!["111\n22\n3333\n", "4\n"]);
+        assert_eq!(split_chunks(input, 4), vec!["111\n22\n", "3333\n", "4\n"]);
+        assert_eq!(split_chunks("12\n34", 8), vec!["12\n", "34"]);
+        assert_eq!(split_chunks("", 4), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn parallel_matches_sequential() {
+        let options = Options::default();
+        let input = "987654321111111\n\n811111111111119\n98a\n234234234234278\n818181911112111\n9";
+
+        let sequential = evaluate_chunk(input, &options, 0, &AtomicUsize::new(usize::MAX));
+        assert_eq!(sequential.lines, 7);
+        assert_eq!(sequential.errors.iter().map(|(line, _)| *line).collect::<Vec<_>>(), vec![1, 3, 6]);
+
+        for threads in 1..8 {
+            assert_eq!(evaluate_parallel(input, &options, threads), sequential);
+        }
+    }
+
+    #[test]
+    fn parallel_fail_keeps_first_error() {
+        let options = Options { on_error: OnError::Fail, ..Default::default() };
+        let input = "987654321111111\n811111111111119\n98a\n234234234234278\n\n818181911112111\n9";
+
+        for threads in 1..8 {
+            let total = evaluate_parallel(input, &options, threads);
+            assert_eq!(total.lines, 3);
+            assert_eq!(total.errors.iter().map(|(line, _)| *line).collect::<Vec<_>>(), vec![2]);
+        }
+    }
+
+    #[test]
+    fn report_rows() {
+        let mut options = Options { on_error: OnError::EnableAll, csv: true, ..Default::default() };
+
+        let row = report_line(0, "818181911112111", &options, 0);
+        assert_eq!(
+            row,
+            ReportRow {
+                line: 1,
+                length: 15,
+                chosen: vec![0, 2, 4, 6, 7, 8, 9, 10, 11, 12, 13, 14],
+                joltage: 888911112111,
+                running_total: 888911112111,
+                error: None
+            }
+        );
+        assert_eq!(
+            row.to_string(),
+            "1,15,12,0 2 4 6 7 8 9 10 11 12 13 14,888911112111,888911112111,"
+        );
+
+        let row = report_line(1, "987", &options, 10);
+        assert_eq!(row.to_string(), "2,3,3,0 1 2,987,997,\"bank has 3 batteries but 12 must be enabled\"");
+
+        options.on_error = OnError::Skip;
+        let row = report_line(2, "9\"7", &options, 10);
+        assert_eq!(row.to_string(), "3,3,0,,0,10,\"column 2: '\"\"' is not a base 10 digit\"");
+    }
+}
@@ -0,0 +1,126 @@
+use grid::Grid;
+
+use crate::Boundary;
+use crate::Rules;
+use crate::Space;
+
+// A group of surviving rolls that touch each other, with the rows and columns
+// it spans.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Component {
+    pub size: usize,
+    pub top: usize,
+    pub left: usize,
+    pub bottom: usize,
+    pub right: usize
+}
+
+// Splits the rolls left on a trimmed board into groups connected through the
+// neighborhood, in the order their first cell appears on the board. Lopsided
+// custom neighborhoods connect two rolls if either counts the other.
+pub fn components(core: &Grid<Space>, rules: &Rules) -> Vec<Component> {
+    let mut seen = Grid::filled(core.rows(), core.columns(), false);
+    let mut components = Vec::new();
+
+    for start in core.positions() {
+        if seen[start] || matches!(core[start], Space::Empty) {
+            continue;
+        }
+
+        seen[start] = true;
+        let mut component = Component { size: 0, top: start.0, left: start.1, bottom: start.0, right: start.1 };
+        let mut todo = vec![start];
+
+        while let Some(position) = todo.pop() {
+            component.size += 1;
+            component.top = component.top.min(position.0);
+            component.left = component.left.min(position.1);
+            component.bottom = component.bottom.max(position.0);
+            component.right = component.right.max(position.1);
+
+            let mut around = rules.neighbors(core, position);
+            around.extend(rules.counted_by(core, position));
+            for next in around {
+                if !seen[next] && matches!(core[next], Space::Roll(_)) {
+                    seen[next] = true;
+                    todo.push(next);
+                }
+            }
+        }
+
+        components.push(component);
+    }
+
+    return components;
+}
+
+// How many neighbors a surviving roll would have to lose before a forklift
+// could reach it, or None if too many of them are in a padded border that
+// never comes off.
+pub fn removals_to_free(core: &Grid<Space>, rules: &Rules, position: (usize, usize)) -> Option<u32> {
+    let Space::Roll(neighbors) = core[position] else {
+        return None;
+    };
+    if neighbors < rules.threshold {
+        return Some(0);
+    }
+
+    let needed = neighbors - rules.threshold + 1;
+    let removable = match rules.boundary {
+        Boundary::Padded => neighbors - rules.border_neighbors(core, position),
+        Boundary::Wall | Boundary::Torus => neighbors
+    };
+
+    if needed > removable {
+        return None;
+    }
+
+    return Some(needed);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_board;
+    use crate::trim_board;
+    use crate::Validation;
+
+    const BOARD: &str = "@@@..\n@@@..\n@@@..\n.....\n...@@\n...@@\n";
+
+    fn trimmed(rules: &Rules) -> Grid<Space> {
+        let mut board = parse_board(BOARD, rules, Validation::Strict).unwrap();
+        trim_board(&mut board, rules);
+        return board;
+    }
+
+    #[test]
+    fn find_components() {
+        let rules = Rules::new(&grid::Neighbourhood::Moore, 3);
+
+        assert_eq!(
+            components(&trimmed(&rules), &rules),
+            vec![
+                Component { size: 9, top: 0, left: 0, bottom: 2, right: 2 },
+                Component { size: 4, top: 4, left: 3, bottom: 5, right: 4 }
+            ]
+        );
+    }
+
+    #[test]
+    fn count_removals() {
+        let rules = Rules::new(&grid::Neighbourhood::Moore, 3);
+        let core = trimmed(&rules);
+
+        assert_eq!(removals_to_free(&core, &rules, (0, 0)), Some(1));
+        assert_eq!(removals_to_free(&core, &rules, (0, 1)), Some(3));
+        assert_eq!(removals_to_free(&core, &rules, (1, 1)), Some(6));
+        assert_eq!(removals_to_free(&core, &rules, (3, 3)), None);
+
+        // A padded corner has 5 neighbors in the border and 3 on the board,
+        // which isn't enough to get below a threshold of 3.
+        let rules = Rules { boundary: Boundary::Padded, ..Rules::new(&grid::Neighbourhood::Moore, 3) };
+        let core = trimmed(&rules);
+        assert_eq!(removals_to_free(&core, &rules, (0, 0)), None);
+        assert_eq!(removals_to_free(&core, &rules, (1, 1)), Some(6));
+    }
+}
@@ -0,0 +1,420 @@
+use std::io;
+use std::io::BufRead;
+use std::mem;
+
+use grid::Grid;
+
+use crate::BoardError;
+use crate::Space;
+
+const WORD_BITS: usize = 64;
+
+// Rolls packed one bit per cell, 64 cells to a word, with every row starting
+// on a fresh word. Neighbor counts are not stored but recomputed 64 cells at a
+// time, which keeps a 100k x 100k board at about 1.25GB. Only the default
+// rules are supported: the Moore neighborhood with walls at the edges.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BitBoard {
+    rows: usize,
+    columns: usize,
+    words_per_row: usize,
+    bits: Vec<u64>
+}
+
+// A small xorshift generator so benchmarks can build large boards without
+// pulling in a dependency.
+pub struct Random(u64);
+
+impl Random {
+    pub fn new(seed: u64) -> Random {
+        Random(seed.max(1))
+    }
+
+    pub fn next_word(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        return self.0;
+    }
+
+    // A word whose bits are each set with the given probability, to within
+    // 1/256.
+    pub fn word_with_density(&mut self, density: f64) -> u64 {
+        let level = (density.clamp(0.0, 1.0) * 256.0).round() as u32;
+        if level >= 256 {
+            return u64::MAX;
+        }
+
+        // Working up from the least significant bit of the density, OR-ing
+        // in a random word adds a half and AND-ing halves what is there.
+        let mut word = 0;
+        for bit in 0..8 {
+            let random = self.next_word();
+            word = if level & (1 << bit) != 0 {
+                word | random
+            } else {
+                word & random
+            };
+        }
+
+        return word;
+    }
+}
+
+// Adds one bit per cell to counts held as bit planes, so plane k holds bit k
+// of each cell's count.
+fn add_to_planes(planes: &mut [u64; 4], bits: u64) {
+    let mut carry = bits;
+    for plane in planes.iter_mut() {
+        let next_carry = *plane & carry;
+        *plane ^= carry;
+        carry = next_carry;
+    }
+}
+
+// The cells whose count, held as bit planes, is below the threshold.
+fn below_threshold(planes: &[u64; 4], threshold: u32) -> u64 {
+    if threshold > 15 {
+        return u64::MAX;
+    }
+
+    let mut below = 0;
+    let mut equal = u64::MAX;
+    for bit in (0..4).rev() {
+        if threshold & (1 << bit) != 0 {
+            below |= equal & !planes[bit];
+            equal &= planes[bit];
+        } else {
+            equal &= !planes[bit];
+        }
+    }
+
+    return below;
+}
+
+// Shifts a row so each cell sees the cell to its left, or its right.
+fn from_left(row: &[u64], word: usize) -> u64 {
+    let carry = if word > 0 { row[word - 1] >> (WORD_BITS - 1) } else { 0 };
+    (row[word] << 1) | carry
+}
+
+fn from_right(row: &[u64], word: usize) -> u64 {
+    let carry = if word + 1 < row.len() { row[word + 1] << (WORD_BITS - 1) } else { 0 };
+    (row[word] >> 1) | carry
+}
+
+impl BitBoard {
+    pub fn new(rows: usize, columns: usize) -> BitBoard {
+        let words_per_row = columns.div_ceil(WORD_BITS);
+        BitBoard {
+            rows,
+            columns,
+            words_per_row,
+            bits: vec![0; rows * words_per_row]
+        }
+    }
+
+    pub fn from_grid(board: &Grid<Space>) -> BitBoard {
+        let mut bits = BitBoard::new(board.rows(), board.columns());
+        for ((row, column), space) in board.iter() {
+            if let Space::Roll(_) = space {
+                bits.set(row, column, true);
+            }
+        }
+        return bits;
+    }
+
+    pub fn random(rows: usize, columns: usize, density: f64, seed: u64) -> BitBoard {
+        let mut board = BitBoard::new(rows, columns);
+        let mut random = Random::new(seed);
+        for row in 0..rows {
+            for word in board.row_mut(row) {
+                *word = random.word_with_density(density);
+            }
+            board.clear_padding(row);
+        }
+        return board;
+    }
+
+    // Streams a board line by line, so the text never has to fit in memory.
+    // Every row must be as long as the first, and blank lines are only
+    // allowed before and after the board.
+    pub fn read<R: BufRead>(reader: R) -> io::Result<Result<BitBoard, BoardError>> {
+        let mut board = BitBoard::new(0, 0);
+        // The first blank line after the board started, if any.
+        let mut blank_line = None;
+
+        for (index, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim_end();
+            let line_number = index + 1;
+
+            if board.rows == 0 {
+                if line.is_empty() {
+                    continue;
+                }
+                board = BitBoard::new(0, line.len());
+            }
+
+            if line.is_empty() {
+                blank_line = blank_line.or(Some(line_number));
+                continue;
+            }
+
+            if let Some(line) = blank_line {
+                return Ok(Err(BoardError::Ragged { line, expected: board.columns, found: 0 }));
+            }
+
+            if let Some((column, found)) = line.chars().enumerate().find(|(_, current)| *current != '@' && *current != '.') {
+                return Ok(Err(BoardError::UnknownCharacter {
+                    line: line_number,
+                    column: column + 1,
+                    found
+                }));
+            }
+
+            if line.len() != board.columns {
+                return Ok(Err(BoardError::Ragged {
+                    line: line_number,
+                    expected: board.columns,
+                    found: line.len()
+                }));
+            }
+
+            board.bits.resize(board.bits.len() + board.words_per_row, 0);
+            board.rows += 1;
+
+            let row = board.rows - 1;
+            for (column, current) in line.bytes().enumerate() {
+                if current == b'@' {
+                    board.set(row, column, true);
+                }
+            }
+        }
+
+        if board.rows == 0 {
+            return Ok(Err(BoardError::Empty));
+        }
+
+        return Ok(Ok(board));
+    }
+
+    pub fn get(&self, row: usize, column: usize) -> bool {
+        let word = self.bits[row * self.words_per_row + column / WORD_BITS];
+        word & (1 << (column % WORD_BITS)) != 0
+    }
+
+    pub fn set(&mut self, row: usize, column: usize, roll: bool) {
+        let word = &mut self.bits[row * self.words_per_row + column / WORD_BITS];
+        let mask = 1 << (column % WORD_BITS);
+        if roll {
+            *word |= mask;
+        } else {
+            *word &= !mask;
+        }
+    }
+
+    fn row(&self, row: usize) -> &[u64] {
+        &self.bits[row * self.words_per_row..(row + 1) * self.words_per_row]
+    }
+
+    fn row_mut(&mut self, row: usize) -> &mut [u64] {
+        &mut self.bits[row * self.words_per_row..(row + 1) * self.words_per_row]
+    }
+
+    // Keeps the bits past the last column clear so they never count as rolls.
+    fn clear_padding(&mut self, row: usize) {
+        let used = self.columns % WORD_BITS;
+        if used != 0 {
+            let last = self.words_per_row - 1;
+            self.row_mut(row)[last] &= (1 << used) - 1;
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = String::with_capacity(self.rows * (self.columns + 1));
+        for row in 0..self.rows {
+            for column in 0..self.columns {
+                text.push(if self.get(row, column) { '@' } else { '.' });
+            }
+            text.push('\n');
+        }
+        return text;
+    }
+
+    pub fn count_rolls(&self) -> u64 {
+        self.bits.iter().map(|word| word.count_ones() as u64).sum()
+    }
+
+    // The accessible rolls of a row given the rows on either side, which are
+    // treated as empty past the edges of the board.
+    fn accessible_in_row(above: Option<&[u64]>, row: &[u64], below: Option<&[u64]>, threshold: u32, out: &mut [u64]) {
+        for word in 0..row.len() {
+            let mut planes = [0; 4];
+            for neighbor_row in [above, below].into_iter().flatten() {
+                add_to_planes(&mut planes, from_left(neighbor_row, word));
+                add_to_planes(&mut planes, neighbor_row[word]);
+                add_to_planes(&mut planes, from_right(neighbor_row, word));
+            }
+            add_to_planes(&mut planes, from_left(row, word));
+            add_to_planes(&mut planes, from_right(row, word));
+
+            out[word] = row[word] & below_threshold(&planes, threshold);
+        }
+    }
+
+    pub fn accessible_rolls(&self, threshold: u32) -> u64 {
+        let mut accessible = vec![0; self.words_per_row];
+        let mut total = 0;
+
+        for row in 0..self.rows {
+            let above = if row > 0 { Some(self.row(row - 1)) } else { None };
+            let below = if row + 1 < self.rows { Some(self.row(row + 1)) } else { None };
+            BitBoard::accessible_in_row(above, self.row(row), below, threshold, &mut accessible);
+            total += accessible.iter().map(|word| word.count_ones() as u64).sum::<u64>();
+        }
+
+        return total;
+    }
+
+    // Removes every accessible roll in synchronous rounds until none are
+    // left, returning how many were removed in each round. Only rows next to
+    // a row that changed in the previous round are looked at again.
+    pub fn trim(&mut self, threshold: u32) -> Vec<u64> {
+        let mut removed_per_round = Vec::new();
+        let mut dirty = vec![true; self.rows];
+        let mut above_before = vec![0; self.words_per_row];
+        let mut row_before = vec![0; self.words_per_row];
+        let mut accessible = vec![0; self.words_per_row];
+
+        loop {
+            let mut removed = 0;
+            let mut changed = vec![false; self.rows];
+            // Whether `above_before` holds the previous row as it was at the
+            // start of this round, which only matters once it has changed.
+            let mut above_saved = false;
+
+            for row in 0..self.rows {
+                if !dirty[row] {
+                    above_saved = false;
+                    continue;
+                }
+
+                row_before.copy_from_slice(self.row(row));
+                let above = if row == 0 {
+                    None
+                } else if above_saved {
+                    Some(&above_before[..])
+                } else {
+                    Some(self.row(row - 1))
+                };
+                let below = if row + 1 < self.rows { Some(self.row(row + 1)) } else { None };
+                BitBoard::accessible_in_row(above, &row_before, below, threshold, &mut accessible);
+
+                let count: u64 = accessible.iter().map(|word| word.count_ones() as u64).sum();
+                if count > 0 {
+                    for (word, remove) in self.row_mut(row).iter_mut().zip(accessible.iter()) {
+                        *word &= !remove;
+                    }
+                    removed += count;
+                    changed[row] = true;
+                }
+
+                mem::swap(&mut above_before, &mut row_before);
+                above_saved = true;
+            }
+
+            if removed == 0 {
+                return removed_per_round;
+            }
+            removed_per_round.push(removed);
+
+            for row in 0..self.rows {
+                dirty[row] = changed[row]
+                    || (row > 0 && changed[row - 1])
+                    || (row + 1 < self.rows && changed[row + 1]);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_board;
+    use crate::test_support::example_input;
+    use crate::trim_board_in_rounds;
+    use crate::Rules;
+    use crate::Validation;
+
+    #[test]
+    fn count_planes() {
+        for count in 0..=8_u32 {
+            let mut planes = [0; 4];
+            for _ in 0..count {
+                add_to_planes(&mut planes, 0b10);
+            }
+            for threshold in 0..=9 {
+                let below = below_threshold(&planes, threshold);
+                assert_eq!(below & 0b10 != 0, count < threshold, "{} < {}", count, threshold);
+                assert_eq!(below & 0b01 != 0, 0 < threshold);
+            }
+        }
+    }
+
+    #[test]
+    fn read_board() {
+        let board = BitBoard::read("\n@.@\n.@.\n\n".as_bytes()).unwrap().unwrap();
+        assert_eq!(board.to_text(), "@.@\n.@.\n");
+        assert_eq!(board.count_rolls(), 3);
+
+        assert_eq!(
+            BitBoard::read("@@\n@\n".as_bytes()).unwrap(),
+            Err(BoardError::Ragged { line: 2, expected: 2, found: 1 })
+        );
+        assert_eq!(
+            BitBoard::read("@@\n@?\n".as_bytes()).unwrap(),
+            Err(BoardError::UnknownCharacter { line: 2, column: 2, found: '?' })
+        );
+        assert_eq!(
+            BitBoard::read("@@\n\n\n@@\n".as_bytes()).unwrap(),
+            Err(BoardError::Ragged { line: 2, expected: 2, found: 0 })
+        );
+    }
+
+    #[test]
+    fn random_density() {
+        let board = BitBoard::random(100, 1000, 0.64, 7);
+        let density = board.count_rolls() as f64 / 100_000.0;
+        assert!((density - 0.64).abs() < 0.01, "{}", density);
+
+        let board = BitBoard::random(3, 70, 1.0, 7);
+        assert_eq!(board.count_rolls(), 210);
+    }
+
+    #[test]
+    fn trim_example() {
+        let input = example_input();
+        let mut board = BitBoard::read(input.as_bytes()).unwrap().unwrap();
+
+        assert_eq!(board.accessible_rolls(4), 13);
+        assert_eq!(board.trim(4), vec![13, 12, 7, 5, 2, 1, 1, 1, 1]);
+        assert_eq!(board.count_rolls(), 71 - 43);
+    }
+
+    #[test]
+    fn trim_matches_grid() {
+        // Wide enough that rows span several words.
+        for (seed, columns) in [(1, 64), (2, 65), (3, 130), (4, 200)] {
+            let mut bits = BitBoard::random(50, columns, 0.64, seed);
+            let rules = Rules::default();
+            let mut board = parse_board(&bits.to_text(), &rules, Validation::Strict).unwrap();
+
+            let rounds = trim_board_in_rounds(&mut board, &rules);
+            let removed: Vec<u64> = rounds.removed_per_round.iter().map(|count| *count as u64).collect();
+
+            assert_eq!(bits.trim(4), removed);
+            assert_eq!(bits, BitBoard::from_grid(&board));
+        }
+    }
+}
@@ -0,0 +1,267 @@
+use std::fmt;
+
+use grid::Grid;
+
+use crate::count_all_neighbors;
+use crate::read_cells;
+use crate::trim_from;
+use crate::BoardError;
+use crate::Rules;
+use crate::Space;
+use crate::Validation;
+
+// A type of roll. Fixed rolls have no threshold: they count as neighbors but
+// are never removed.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Kind {
+    pub name: String,
+    pub threshold: Option<u32>
+}
+
+// Which characters on the board stand for which kinds of roll. Characters
+// that map to no kind are empty floor.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Legend {
+    symbols: Vec<(char, Option<usize>)>,
+    kinds: Vec<Kind>
+}
+
+#[derive(Debug, PartialEq)]
+pub enum LegendError {
+    Malformed { line: usize },
+    UnknownBehaviour { line: usize, found: String },
+    Duplicate { line: usize, symbol: char }
+}
+
+impl fmt::Display for LegendError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LegendError::Malformed { line } => {
+                write!(f, "line {}: expected a character, a name and a threshold, roll, fixed or empty", line)
+            },
+            LegendError::UnknownBehaviour { line, found } => {
+                write!(f, "line {}: {:?} is not a threshold, roll, fixed or empty", line, found)
+            },
+            LegendError::Duplicate { line, symbol } => {
+                write!(f, "line {}: {:?} is already in the legend", line, symbol)
+            }
+        }
+    }
+}
+
+// How many rolls of a kind there are and how many of them can be reached.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Tally {
+    pub rolls: usize,
+    pub accessible: usize
+}
+
+impl Legend {
+    // Reads one entry per line, as a character, a name and what it does:
+    //
+    // ```text
+    // @ paper 4
+    // % cardboard roll
+    // # pillar fixed
+    // . floor empty
+    // ```
+    //
+    // A number is the kind's threshold, `roll` uses the default threshold,
+    // `fixed` never comes off and `empty` is floor.
+    pub fn parse(input: &str, default_threshold: u32) -> Result<Legend, Vec<LegendError>> {
+        let mut legend = Legend { symbols: Vec::new(), kinds: Vec::new() };
+        let mut errors = Vec::new();
+
+        for (index, line) in input.lines().enumerate() {
+            let line_number = index + 1;
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let (symbol, name, behaviour) = match fields[..] {
+                [] => continue,
+                [symbol, name, behaviour] if symbol.chars().count() == 1 => {
+                    (symbol.chars().next().unwrap(), name, behaviour)
+                },
+                _ => {
+                    errors.push(LegendError::Malformed { line: line_number });
+                    continue;
+                }
+            };
+
+            if legend.lookup(symbol).is_some() {
+                errors.push(LegendError::Duplicate { line: line_number, symbol });
+                continue;
+            }
+
+            let threshold = match behaviour {
+                "empty" => {
+                    legend.symbols.push((symbol, None));
+                    continue;
+                },
+                "fixed" => None,
+                "roll" => Some(default_threshold),
+                _ => match behaviour.parse() {
+                    Ok(threshold) => Some(threshold),
+                    Err(_) => {
+                        errors.push(LegendError::UnknownBehaviour { line: line_number, found: behaviour.to_string() });
+                        continue;
+                    }
+                }
+            };
+
+            legend.symbols.push((symbol, Some(legend.kinds.len())));
+            legend.kinds.push(Kind { name: name.to_string(), threshold });
+        }
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        return Ok(legend);
+    }
+
+    pub fn kinds(&self) -> &[Kind] {
+        &self.kinds
+    }
+
+    // The kind a character stands for, or None inside if it is empty floor.
+    fn lookup(&self, symbol: char) -> Option<Option<usize>> {
+        self.symbols.iter().find(|(known, _)| *known == symbol).map(|(_, kind)| *kind)
+    }
+}
+
+// A board with several kinds of roll. The board stores neighbor counts for
+// every roll, fixed or not, and the kinds grid says which kind each one is.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TypedBoard {
+    legend: Legend,
+    board: Grid<Space>,
+    kinds: Grid<Option<usize>>
+}
+
+impl TypedBoard {
+    pub fn parse(input: &str, legend: Legend, rules: &Rules, validation: Validation) -> Result<TypedBoard, Vec<BoardError>> {
+        let kinds = read_cells(input, validation, None, |current, line, column, errors| {
+            legend.lookup(current).unwrap_or_else(|| {
+                errors.push(BoardError::UnknownCharacter { line, column, found: current });
+                None
+            })
+        })?;
+
+        let cells = kinds.cells().iter().map(|kind| if kind.is_some() { Space::Roll(0) } else { Space::Empty }).collect();
+        let mut board = Grid::new(kinds.rows(), kinds.columns(), cells);
+        count_all_neighbors(&mut board, rules);
+
+        return Ok(TypedBoard { legend, board, kinds });
+    }
+
+    fn is_accessible(&self, position: (usize, usize)) -> bool {
+        is_accessible(&self.legend, self.kinds[position], &self.board[position])
+    }
+
+    // Rolls left and rolls accessible for each kind in the legend.
+    pub fn tally(&self) -> Vec<Tally> {
+        let mut tallies = vec![Tally::default(); self.legend.kinds.len()];
+
+        for (position, space) in self.board.iter() {
+            let (Space::Roll(_), Some(kind)) = (space, self.kinds[position]) else {
+                continue;
+            };
+
+            tallies[kind].rolls += 1;
+            if self.is_accessible(position) {
+                tallies[kind].accessible += 1;
+            }
+        }
+
+        return tallies;
+    }
+
+    // Removes accessible rolls until none are left, with each roll judged by
+    // its own kind's threshold.
+    pub fn trim(&mut self, rules: &Rules) {
+        let todo: Vec<(usize, usize)> = self.board.positions().collect();
+        let (legend, kinds) = (&self.legend, &self.kinds);
+        trim_from(&mut self.board, rules, todo, |space, position| is_accessible(legend, kinds[position], space));
+    }
+}
+
+// Rolls of a kind with no threshold can never be reached.
+fn is_accessible(legend: &Legend, kind: Option<usize>, space: &Space) -> bool {
+    let threshold = kind.and_then(|kind| legend.kinds[kind].threshold);
+    match (space, threshold) {
+        (Space::Roll(neighbors), Some(threshold)) => *neighbors < threshold,
+        _ => false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::example_input;
+
+    const LEGEND: &str = "@ paper 4\n$ crate 9\n\n# pillar fixed\n_ floor empty\n";
+    const BOARD: &str = "#@#\n@$@\n#@#\n";
+
+    fn tally(rolls: usize, accessible: usize) -> Tally {
+        Tally { rolls, accessible }
+    }
+
+    #[test]
+    fn parse_legend() {
+        let legend = Legend::parse(LEGEND, 3).unwrap();
+        assert_eq!(legend.kinds().len(), 3);
+        assert_eq!(legend.kinds()[1], Kind { name: String::from("crate"), threshold: Some(9) });
+        assert_eq!(legend.kinds()[2].threshold, None);
+        assert_eq!(legend.lookup('_'), Some(None));
+        assert_eq!(legend.lookup('.'), None);
+
+        assert_eq!(Legend::parse("@ paper roll\n", 3).unwrap().kinds()[0].threshold, Some(3));
+        assert_eq!(
+            Legend::parse("@ paper 4\n@ card 2\nab paper 4\n# pillar solid\n", 3),
+            Err(vec![
+                LegendError::Duplicate { line: 2, symbol: '@' },
+                LegendError::Malformed { line: 3 },
+                LegendError::UnknownBehaviour { line: 4, found: String::from("solid") }
+            ])
+        );
+    }
+
+    #[test]
+    fn default_legend_matches_example() {
+        let rules = Rules::default();
+        let input = example_input();
+        let legend = Legend::parse("@ roll roll\n. floor empty\n", 4).unwrap();
+        let mut board = TypedBoard::parse(&input, legend, &rules, Validation::Strict).unwrap();
+
+        assert_eq!(board.tally(), vec![tally(71, 13)]);
+        board.trim(&rules);
+        assert_eq!(board.tally()[0].rolls, 71 - 43);
+    }
+
+    #[test]
+    fn trim_by_kind() {
+        let rules = Rules::default();
+        let mut board = TypedBoard::parse(BOARD, Legend::parse(LEGEND, 4).unwrap(), &rules, Validation::Strict).unwrap();
+
+        // The crate has 8 neighbors, under its threshold of 9, while each
+        // paper roll has 5 and the pillars never come off.
+        assert_eq!(board.tally(), vec![tally(4, 0), tally(1, 1), tally(4, 0)]);
+        board.trim(&rules);
+        assert_eq!(board.tally(), vec![tally(4, 0), tally(0, 0), tally(4, 0)]);
+
+        // With the crate gone the paper rolls drop to 4 neighbors.
+        let legend = Legend::parse(&LEGEND.replace("paper 4", "paper 5"), 4).unwrap();
+        let mut board = TypedBoard::parse(BOARD, legend, &rules, Validation::Strict).unwrap();
+        board.trim(&rules);
+        assert_eq!(board.tally(), vec![tally(0, 0), tally(0, 0), tally(4, 0)]);
+    }
+
+    #[test]
+    fn unknown_symbol() {
+        let legend = Legend::parse(LEGEND, 4).unwrap();
+
+        assert_eq!(
+            TypedBoard::parse("#@\n@.\n", legend, &Rules::default(), Validation::Strict),
+            Err(vec![BoardError::UnknownCharacter { line: 2, column: 2, found: '.' }])
+        );
+    }
+}
@@ -1,196 +1,1089 @@
+#![allow(clippy::needless_return)]
+
+mod analysis;
+mod bitboard;
+mod legend;
+mod render;
+mod stack;
+#[cfg(test)]
+mod test_support;
+mod warehouse;
+
+use std::collections::BTreeMap;
 use std::env;
+use std::fmt;
 use std::fs;
+use std::fs::File;
+use std::io::BufReader;
+use std::io::BufWriter;
+use std::path::Path;
+use std::process;
+use std::time::Instant;
+
+use grid::Grid;
+use grid::Neighbourhood;
+
+use bitboard::BitBoard;
+use legend::Legend;
+use legend::TypedBoard;
+use stack::Stack;
+use stack::StackRules;
+use warehouse::Warehouse;
 
+const IMAGE_SCALE: usize = 4;
+
+// Generated benchmark boards match the density of rolls in the real input.
+const BENCH_DENSITY: f64 = 0.64;
+const BENCH_SEED: u64 = 2025;
+// Above this many cells the benchmark skips the grid, which stores 8 bytes
+// per cell.
+const BENCH_GRID_LIMIT: usize = 100_000_000;
+
+#[derive(Clone, Debug, PartialEq)]
 enum Space {
     Empty,
     Roll(u32)
 }
 
-fn is_left_edge(index: usize, columns: usize) -> bool {
-    index % columns == 0
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Validation {
+    // Every row must be as long as the first.
+    Strict,
+    // Short rows are padded with empty floor to the longest row.
+    Lenient
 }
 
-fn is_right_edge(index: usize, columns: usize) -> bool {
-    index % columns == columns - 1
+// Lines and columns count from 1, as in an editor.
+#[derive(Debug, PartialEq)]
+enum BoardError {
+    Empty,
+    Ragged { line: usize, expected: usize, found: usize },
+    UnknownCharacter { line: usize, column: usize, found: char },
+    // Layers of a stack disagree on their number of rows.
+    LayerRows { line: usize, expected: usize, found: usize },
+    // A run of blank lines between layers doesn't fit the stack's shape.
+    Uneven { line: usize, expected: usize, found: usize },
+    ShortGroup { line: usize }
 }
 
-fn is_top_edge(index: usize, columns: usize) -> bool {
-    index < columns
+impl fmt::Display for BoardError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BoardError::Empty => write!(f, "the board has no rows"),
+            BoardError::Ragged { line, expected, found } => {
+                write!(f, "line {}: row has {} cells but {} were expected", line, found, expected)
+            },
+            BoardError::UnknownCharacter { line, column, found } => {
+                write!(f, "line {}, column {}: unknown character {:?}", line, column, found)
+            },
+            BoardError::LayerRows { line, expected, found } => {
+                write!(f, "line {}: layer has {} rows but {} were expected", line, found, expected)
+            },
+            BoardError::Uneven { line, expected, found } => {
+                write!(f, "line {}: {} blank lines before this layer but {} were expected", line, found, expected)
+            },
+            BoardError::ShortGroup { line } => {
+                write!(f, "line {}: the last group of layers is shorter than the others", line)
+            }
+        }
+    }
 }
 
-fn is_bottom_edge(index: usize, columns: usize, board_size: usize) -> bool {
-    index + columns >= board_size
+// What lies beyond the edges of the board.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Boundary {
+    // Nothing, so edge rolls have fewer neighbors.
+    Wall,
+    // The opposite edge, as if the board were a torus.
+    Torus,
+    // A border of rolls that can never be removed.
+    Padded
 }
 
-fn parse_board(input: &str) -> (Vec<Space>, usize) {
-    let columns = input.lines().take(1).next().unwrap().trim().len();
-    let mut board: Vec<Space> = Vec::with_capacity(input.len());
+// Which rolls count as neighbors and how many of them keep a roll out of
+// reach.
+#[derive(Clone, Debug, PartialEq)]
+struct Rules {
+    threshold: u32,
+    offsets: Vec<(isize, isize)>,
+    // Offsets back to the cells that count a given cell as a neighbor. These
+    // only differ from `offsets` for lopsided custom neighborhoods.
+    reverse_offsets: Vec<(isize, isize)>,
+    boundary: Boundary
+}
 
-    let mut index = 0;
-    for current in input.trim().chars() {
-        if current.is_whitespace() {
-            continue;
+impl Rules {
+    fn new(neighbourhood: &Neighbourhood, threshold: u32) -> Rules {
+        let offsets = neighbourhood.offsets();
+        let reverse_offsets = offsets.iter().map(|(row, column)| (-row, -column)).collect();
+        Rules {
+            threshold,
+            offsets,
+            reverse_offsets,
+            boundary: Boundary::Wall
         }
+    }
 
-        board.push(match current {
-            '@' => Space::Roll(0),
-            _ => Space::Empty
-        });
+    fn lookup<T>(&self, board: &Grid<T>, position: (usize, usize), offsets: &[(isize, isize)]) -> Vec<(usize, usize)> {
+        match self.boundary {
+            Boundary::Torus => board.wrapping_neighbours(position.0, position.1, offsets).collect(),
+            Boundary::Wall | Boundary::Padded => board.neighbours(position.0, position.1, offsets).collect()
+        }
+    }
 
-        let space = &mut board[index];
-        if let Space::Empty = space {
-            index += 1;
-            continue;
+    // The cells on the board that a roll at `position` counts as neighbors.
+    fn neighbors<T>(&self, board: &Grid<T>, position: (usize, usize)) -> Vec<(usize, usize)> {
+        self.lookup(board, position, &self.offsets)
+    }
+
+    // The cells on the board that count a roll at `position` as a neighbor.
+    fn counted_by<T>(&self, board: &Grid<T>, position: (usize, usize)) -> Vec<(usize, usize)> {
+        self.lookup(board, position, &self.reverse_offsets)
+    }
+
+    // How many of a roll's neighbors are in the padded border.
+    fn border_neighbors<T>(&self, board: &Grid<T>, position: (usize, usize)) -> u32 {
+        if self.boundary != Boundary::Padded {
+            return 0;
         }
+        let on_board = board.neighbours(position.0, position.1, &self.offsets).count();
+        return (self.offsets.len() - on_board) as u32;
+    }
+}
 
-        let mut neighbors = 0;
+impl Default for Rules {
+    fn default() -> Self {
+        Rules::new(&Neighbourhood::Moore, 4)
+    }
+}
 
-        if !is_left_edge(index, columns) {
-            if let Space::Roll(their_neighbors) = board[index - 1] {
-                neighbors += 1;
-                board[index - 1] = Space::Roll(their_neighbors + 1);
-            }
+// The result of trimming in synchronous rounds, where every roll accessible at
+// the start of a round is removed at once.
+#[derive(Debug, PartialEq)]
+struct Rounds {
+    removed_per_round: Vec<usize>,
+    // The round, counting from 1, in which each roll was removed.
+    removed_in: Grid<Option<usize>>
+}
+
+#[derive(Debug, PartialEq)]
+struct Options {
+    file_name: String,
+    rules: Rules,
+    neighbourhood: Neighbourhood,
+    validation: Validation,
+    rounds: bool,
+    render: bool,
+    heatmap: Option<String>,
+    frames: Option<String>,
+    bitboard: bool,
+    bench: Option<(usize, usize)>,
+    updates: Option<String>,
+    layers: bool,
+    legend: Option<String>,
+    core: bool
+}
+
+fn read_rows(input: &str, validation: Validation) -> Result<Grid<Space>, Vec<BoardError>> {
+    read_cells(input, validation, Space::Empty, read_cell)
+}
+
+// Reads the rows of a board, ignoring blank lines before and after it, and
+// reports every problem found rather than only the first. Each character is
+// read with the line and column it sits at, and short rows in lenient mode are
+// filled with the padding.
+fn read_cells<T, F>(input: &str, validation: Validation, padding: T, read: F) -> Result<Grid<T>, Vec<BoardError>>
+where
+    T: Clone,
+    F: Fn(char, usize, usize, &mut Vec<BoardError>) -> T
+{
+    let lines: Vec<(usize, &str)> = input
+        .lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.trim_end()))
+        .skip_while(|(_, line)| line.is_empty())
+        .collect();
+    let last_row = lines.iter().rposition(|(_, line)| !line.is_empty());
+    let lines = match last_row {
+        Some(last_row) => &lines[..=last_row],
+        None => return Err(vec![BoardError::Empty])
+    };
+
+    let lengths = lines.iter().map(|(_, line)| line.chars().count());
+    let columns = match validation {
+        Validation::Strict => lengths.take(1).sum(),
+        Validation::Lenient => lengths.max().unwrap_or(0)
+    };
+
+    let mut errors = Vec::new();
+    let mut cells = Vec::with_capacity(lines.len() * columns);
+    for (line_number, line) in lines {
+        let found = line.chars().count();
+        if found != columns && validation == Validation::Strict {
+            errors.push(BoardError::Ragged { line: *line_number, expected: columns, found });
         }
 
-        if !is_top_edge(index, columns) {
-            if let Space::Roll(their_neighbors) = board[index - columns] {
-                neighbors += 1;
-                board[index - columns] = Space::Roll(their_neighbors + 1);
-            }
+        for (index, current) in line.chars().enumerate() {
+            cells.push(read(current, *line_number, index + 1, &mut errors));
         }
 
-        if !is_left_edge(index, columns) && !is_top_edge(index, columns) {
-            if let Space::Roll(their_neighbors) = board[index - columns - 1] {
-                neighbors += 1;
-                board[index - columns - 1] = Space::Roll(their_neighbors + 1);
-            }
+        for _ in found..columns {
+            cells.push(padding.clone());
         }
+    }
 
-        if !is_right_edge(index, columns) && !is_top_edge(index, columns) {
-            if let Space::Roll(their_neighbors) = board[index - columns + 1] {
-                neighbors += 1;
-                board[index - columns + 1] = Space::Roll(their_neighbors + 1);
-            }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    return Ok(Grid::new(lines.len(), columns, cells));
+}
+
+fn read_cell(current: char, line: usize, column: usize, errors: &mut Vec<BoardError>) -> Space {
+    match current {
+        '@' => Space::Roll(0),
+        '.' => Space::Empty,
+        _ => {
+            errors.push(BoardError::UnknownCharacter { line, column, found: current });
+            Space::Empty
         }
+    }
+}
+
+fn parse_board(input: &str, rules: &Rules, validation: Validation) -> Result<Grid<Space>, Vec<BoardError>> {
+    let mut board = read_rows(input, validation)?;
+    count_all_neighbors(&mut board, rules);
+    return Ok(board);
+}
 
-        board[index] = Space::Roll(neighbors);
-        index += 1;
-    } 
+fn count_all_neighbors(board: &mut Grid<Space>, rules: &Rules) {
+    let positions: Vec<(usize, usize)> = board.positions().collect();
+    for position in positions {
+        if let Space::Empty = board[position] {
+            continue;
+        }
 
-    return (board, columns);
+        board[position] = Space::Roll(count_neighbors(board, rules, position));
+    }
 }
 
-fn trim_board(board: &mut Vec<Space>, columns: usize) {
-    let mut todo: Vec<usize> = (0..board.len()).collect();
-    while !todo.is_empty() {
-        let index = todo.pop().unwrap();
-        let space = &board[index];
+fn count_neighbors(board: &Grid<Space>, rules: &Rules, position: (usize, usize)) -> u32 {
+    let neighbors = rules
+        .neighbors(board, position)
+        .iter()
+        .filter(|neighbor| matches!(board[**neighbor], Space::Roll(_)))
+        .count();
 
-        if let Space::Roll(neighbors) = *space {
-            if neighbors >= 4 {
-                continue;
-            }
-            
-            board[index] = Space::Empty;
+    return neighbors as u32 + rules.border_neighbors(board, position);
+}
 
-            if !is_left_edge(index, columns) {
-                let neighbor = index - 1;
-                if let Space::Roll(their_neighbors) = board[neighbor] {
-                    board[neighbor] = Space::Roll(their_neighbors - 1);
-                    todo.push(neighbor);
-                }
-            }
+// Puts a roll in an empty cell and counts it in the rolls around it, returning
+// the rolls whose counts went up.
+fn place_roll(board: &mut Grid<Space>, rules: &Rules, position: (usize, usize)) -> Vec<(usize, usize)> {
+    board[position] = Space::Roll(0);
+    board[position] = Space::Roll(count_neighbors(board, rules, position));
 
-            if !is_right_edge(index, columns) {
-                let neighbor = index + 1;
-                if let Space::Roll(their_neighbors) = board[neighbor] {
-                    board[neighbor] = Space::Roll(their_neighbors - 1);
-                    todo.push(neighbor);
-                }
-            }
+    let mut touched = Vec::new();
+    for neighbor in rules.counted_by(board, position) {
+        if neighbor == position {
+            continue;
+        }
+        if let Space::Roll(their_neighbors) = board[neighbor] {
+            board[neighbor] = Space::Roll(their_neighbors + 1);
+            touched.push(neighbor);
+        }
+    }
+
+    return touched;
+}
+
+// Takes a roll away and stops counting it in the rolls around it, returning
+// the rolls whose counts went down.
+fn take_roll(board: &mut Grid<Space>, rules: &Rules, position: (usize, usize)) -> Vec<(usize, usize)> {
+    board[position] = Space::Empty;
+
+    let mut touched = Vec::new();
+    for neighbor in rules.counted_by(board, position) {
+        if let Space::Roll(their_neighbors) = board[neighbor] {
+            board[neighbor] = Space::Roll(their_neighbors - 1);
+            touched.push(neighbor);
+        }
+    }
+
+    return touched;
+}
+
+// A forklift can reach a roll with fewer neighboring rolls than the threshold.
+fn is_accessible(space: &Space, rules: &Rules) -> bool {
+    matches!(*space, Space::Roll(neighbors) if neighbors < rules.threshold)
+}
+
+fn accessible_rolls(board: &Grid<Space>, rules: &Rules) -> usize {
+    board.cells().iter().filter(|space| is_accessible(space, rules)).count()
+}
+
+fn trim_board(board: &mut Grid<Space>, rules: &Rules) {
+    let todo: Vec<(usize, usize)> = board.positions().collect();
+    trim_from(board, rules, todo, |space, _| is_accessible(space, rules));
+}
+
+// Trims the board, only looking at the given rolls and any rolls whose counts
+// change along the way. `accessible` decides whether the roll in a cell can be
+// taken. Returns the rolls taken, in the order they went.
+fn trim_from<F>(board: &mut Grid<Space>, rules: &Rules, mut todo: Vec<(usize, usize)>, accessible: F) -> Vec<(usize, usize)>
+where
+    F: Fn(&Space, (usize, usize)) -> bool
+{
+    let mut removed = Vec::new();
+
+    while let Some(position) = todo.pop() {
+        if !accessible(&board[position], position) {
+            continue;
+        }
+
+        todo.extend(take_roll(board, rules, position));
+        removed.push(position);
+    }
+
+    return removed;
+}
+
+fn trim_board_in_rounds(board: &mut Grid<Space>, rules: &Rules) -> Rounds {
+    let mut removed_in = Grid::filled(board.rows(), board.columns(), None);
+    let mut removed_per_round = Vec::new();
+
+    let mut wave: Vec<(usize, usize)> = board
+        .positions()
+        .filter(|position| is_accessible(&board[*position], rules))
+        .collect();
 
-            if !is_top_edge(index, columns) {
-                let neighbor = index - columns;
+    while !wave.is_empty() {
+        let round = removed_per_round.len() + 1;
+        removed_per_round.push(wave.len());
+
+        for position in &wave {
+            board[*position] = Space::Empty;
+            removed_in[*position] = Some(round);
+        }
+
+        let mut next_wave = Vec::new();
+        for position in &wave {
+            for neighbor in rules.counted_by(board, *position) {
                 if let Space::Roll(their_neighbors) = board[neighbor] {
                     board[neighbor] = Space::Roll(their_neighbors - 1);
-                    todo.push(neighbor);
+                    next_wave.push(neighbor);
                 }
             }
+        }
 
-            if !is_left_edge(index, columns) && !is_top_edge(index, columns) {
-                let neighbor = index - columns - 1;
-                if let Space::Roll(their_neighbors) = board[neighbor] {
-                    board[neighbor] = Space::Roll(their_neighbors - 1);
-                    todo.push(neighbor);
-                }
+        next_wave.sort();
+        next_wave.dedup();
+        next_wave.retain(|position| is_accessible(&board[*position], rules));
+        wave = next_wave;
+    }
+
+    return Rounds { removed_per_round, removed_in };
+}
+
+fn count_rolls(board: &Grid<Space>) -> usize {
+    board.cells().iter().fold(0, |acc, space| {
+        if let Space::Roll(_) = *space {
+            acc + 1
+        } else {
+            acc
+        }
+    })
+}
+
+fn parse_neighbourhood(value: &str) -> Neighbourhood {
+    match value {
+        "von-neumann" => Neighbourhood::VonNeumann,
+        "moore" => Neighbourhood::Moore,
+        _ => {
+            let radius = value
+                .strip_prefix("radius:")
+                .and_then(|radius| radius.parse().ok())
+                .expect("--neighbourhood expects von-neumann, moore or radius:<r>");
+            Neighbourhood::Radius(radius)
+        }
+    }
+}
+
+fn parse_args(args: &[String]) -> Options {
+    let mut file_name = String::new();
+    let mut neighbourhood = Neighbourhood::Moore;
+    let mut threshold = 4;
+    let mut boundary = Boundary::Wall;
+    let mut validation = Validation::Strict;
+    let mut rounds = false;
+    let mut render = false;
+    let mut heatmap = None;
+    let mut frames = None;
+    let mut bitboard = false;
+    let mut bench = None;
+    let mut updates = None;
+    let mut layers = false;
+    let mut legend = None;
+    let mut core = false;
+
+    let mut args = args.iter().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--threshold" => {
+                threshold = args.next()
+                    .and_then(|value| value.parse().ok())
+                    .expect("--threshold expects a number");
+            },
+            "--neighbourhood" => {
+                neighbourhood = parse_neighbourhood(args.next().map_or("", |value| value.as_str()));
+            },
+            "--boundary" => {
+                boundary = match args.next().map(|value| value.as_str()) {
+                    Some("wall") => Boundary::Wall,
+                    Some("torus") => Boundary::Torus,
+                    Some("padded") => Boundary::Padded,
+                    _ => panic!("--boundary expects wall, torus or padded")
+                };
+            },
+            "--mask" => {
+                let mask_file = args.next().expect("--mask expects a file");
+                let mask = fs::read_to_string(mask_file).unwrap();
+                neighbourhood = Neighbourhood::from_mask(&mask)
+                    .unwrap_or_else(|error| panic!("{}: {}", mask_file, error));
+            },
+            "--lenient" => validation = Validation::Lenient,
+            "--rounds" => rounds = true,
+            "--render" => render = true,
+            "--heatmap" => heatmap = Some(args.next().expect("--heatmap expects a file").clone()),
+            "--frames" => frames = Some(args.next().expect("--frames expects a directory").clone()),
+            "--bitboard" => bitboard = true,
+            "--layers" => layers = true,
+            "--core" => core = true,
+            "--legend" => legend = Some(args.next().expect("--legend expects a file").clone()),
+            "--updates" => updates = Some(args.next().expect("--updates expects a file").clone()),
+            "--bench" => {
+                bench = args.next()
+                    .and_then(|value| value.split_once('x'))
+                    .and_then(|(rows, columns)| Some((rows.parse().ok()?, columns.parse().ok()?)));
+                assert!(bench.is_some(), "--bench expects <rows>x<columns>");
+            },
+            _ => file_name = arg.clone()
+        }
+    }
+
+    let mut rules = Rules::new(&neighbourhood, threshold);
+    rules.boundary = boundary;
+
+    return Options {
+        file_name,
+        rules,
+        neighbourhood,
+        validation,
+        rounds,
+        render,
+        heatmap,
+        frames,
+        bitboard,
+        bench,
+        updates,
+        layers,
+        legend,
+        core
+    };
+}
+
+// The mode that runs and the flags given alongside it that it has no use for.
+// Modes are picked in the order main checks them.
+fn ignored_flags(options: &Options) -> (&'static str, Vec<&'static str>) {
+    let (mode, supported): (&str, &[&str]) = if options.bench.is_some() {
+        ("--bench", &["--bench"])
+    } else if options.bitboard {
+        ("--bitboard", &["--bitboard", "--rounds"])
+    } else if options.layers {
+        ("--layers", &["--layers", "--lenient"])
+    } else if options.legend.is_some() {
+        ("--legend", &["--legend", "--lenient"])
+    } else if options.updates.is_some() {
+        ("--updates", &["--updates", "--render", "--lenient"])
+    } else {
+        return ("", Vec::new());
+    };
+
+    let given = [
+        ("--bench", options.bench.is_some()),
+        ("--bitboard", options.bitboard),
+        ("--layers", options.layers),
+        ("--legend", options.legend.is_some()),
+        ("--updates", options.updates.is_some()),
+        ("--core", options.core),
+        ("--rounds", options.rounds),
+        ("--render", options.render),
+        ("--heatmap", options.heatmap.is_some()),
+        ("--frames", options.frames.is_some()),
+        ("--lenient", options.validation == Validation::Lenient)
+    ];
+    let ignored = given
+        .iter()
+        .filter(|(flag, set)| *set && !supported.contains(flag))
+        .map(|(flag, _)| *flag)
+        .collect();
+
+    return (mode, ignored);
+}
+
+fn require_bitboard_rules(rules: &Rules) {
+    if rules.offsets != Neighbourhood::Moore.offsets() || rules.boundary != Boundary::Wall {
+        eprintln!("The bit-packed board only supports the moore neighbourhood with wall boundaries");
+        process::exit(1);
+    }
+}
+
+fn run_bitboard(options: &Options) {
+    require_bitboard_rules(&options.rules);
+
+    let file = File::open(&options.file_name).unwrap();
+    let mut board = match BitBoard::read(BufReader::new(file)).unwrap() {
+        Ok(board) => board,
+        Err(error) => {
+            eprintln!("{}: {}", options.file_name, error);
+            process::exit(1);
+        }
+    };
+
+    let accessible = board.accessible_rolls(options.rules.threshold);
+    let removed_per_round = board.trim(options.rules.threshold);
+
+    if options.rounds {
+        for (index, removed) in removed_per_round.iter().enumerate() {
+            println!("Round {}: removed {}", index + 1, removed);
+        }
+        println!("Rounds: {}", removed_per_round.len());
+    }
+
+    println!("Accessible: {}", accessible);
+    println!("Gettable: {}", removed_per_round.iter().sum::<u64>());
+}
+
+// Times parsing and trimming a generated board with the grid and with the
+// bit-packed board, and checks that both remove the same rolls.
+fn run_benchmark(rows: usize, columns: usize, rules: &Rules) {
+    require_bitboard_rules(rules);
+
+    let mut bits = BitBoard::random(rows, columns, BENCH_DENSITY, BENCH_SEED);
+    println!("Board: {}x{} with {} rolls", rows, columns, bits.count_rolls());
+
+    let text = if rows * columns <= BENCH_GRID_LIMIT { Some(bits.to_text()) } else { None };
+
+    let start = Instant::now();
+    let removed: u64 = bits.trim(rules.threshold).iter().sum();
+    let bits_time = start.elapsed();
+    println!("Bit board: removed {} in {:.3}s", removed, bits_time.as_secs_f64());
+
+    let Some(text) = text else {
+        println!("Grid: skipped above {} cells", BENCH_GRID_LIMIT);
+        return;
+    };
+
+    let start = Instant::now();
+    let mut board = parse_board(&text, rules, Validation::Strict).unwrap();
+    let rolls = count_rolls(&board);
+    trim_board(&mut board, rules);
+    let grid_removed = rolls - count_rolls(&board);
+    let grid_time = start.elapsed();
+    println!("Grid: removed {} in {:.3}s", grid_removed, grid_time.as_secs_f64());
+
+    assert_eq!(removed, grid_removed as u64, "the boards removed different numbers of rolls");
+    assert!(BitBoard::from_grid(&board) == bits, "the boards were left in different states");
+    println!("Speedup: {:.1}x", grid_time.as_secs_f64() / bits_time.as_secs_f64());
+}
+
+// Reads a stack of layers, where one blank line separates layers, two separate
+// stacks of layers, and so on for each extra dimension.
+fn run_stack(options: &Options) {
+    if options.rules.boundary != Boundary::Wall {
+        eprintln!("Stacked boards only support wall boundaries");
+        process::exit(1);
+    }
+
+    let file = fs::read_to_string(&options.file_name).unwrap();
+    let mut stack = match Stack::read(&file, options.validation) {
+        Ok(stack) => stack,
+        Err(errors) => {
+            for error in errors {
+                eprintln!("{}: {}", options.file_name, error);
             }
+            process::exit(1);
+        }
+    };
 
-            if !is_right_edge(index, columns) && !is_top_edge(index, columns) {
-                let neighbor = index - columns + 1;
-                if let Space::Roll(their_neighbors) = board[neighbor] {
-                    board[neighbor] = Space::Roll(their_neighbors - 1);
-                    todo.push(neighbor);
-                }
+    let Some(rules) = StackRules::from_neighbourhood(&options.neighbourhood, stack.dimensions(), options.rules.threshold) else {
+        eprintln!("Stacked boards don't support custom neighbourhoods");
+        process::exit(1);
+    };
+
+    stack.count_neighbors(&rules);
+    let rolls = stack.count_rolls();
+    let accessible = stack.accessible_rolls(&rules);
+    stack.trim(&rules);
+
+    println!("Shape: {}", stack.shape().iter().map(|size| size.to_string()).collect::<Vec<_>>().join("x"));
+    println!("Accessible: {}", accessible);
+    println!("Gettable: {}", rolls - stack.count_rolls());
+}
+
+// Trims a board drawn with a custom legend and reports each kind of roll on
+// its own, followed by the totals over the kinds that can be removed.
+fn run_legend(options: &Options, legend_file: &str) {
+    let legend = match Legend::parse(&fs::read_to_string(legend_file).unwrap(), options.rules.threshold) {
+        Ok(legend) => legend,
+        Err(errors) => {
+            for error in errors {
+                eprintln!("{}: {}", legend_file, error);
             }
+            process::exit(1);
+        }
+    };
+    let kinds = legend.kinds().to_vec();
 
-            if !is_bottom_edge(index, columns, board.len()) {
-                let neighbor = index + columns;
-                if let Space::Roll(their_neighbors) = board[neighbor] {
-                    board[neighbor] = Space::Roll(their_neighbors - 1);
-                    todo.push(neighbor);
-                }
+    let file = fs::read_to_string(&options.file_name).unwrap();
+    let mut board = match TypedBoard::parse(&file, legend, &options.rules, options.validation) {
+        Ok(board) => board,
+        Err(errors) => {
+            for error in errors {
+                eprintln!("{}: {}", options.file_name, error);
             }
+            process::exit(1);
+        }
+    };
 
-            if !is_bottom_edge(index, columns, board.len()) && !is_left_edge(index, columns) {
-                let neighbor = index + columns - 1;
-                if let Space::Roll(their_neighbors) = board[neighbor] {
-                    board[neighbor] = Space::Roll(their_neighbors - 1);
-                    todo.push(neighbor);
-                }
+    let before = board.tally();
+    board.trim(&options.rules);
+    let after = board.tally();
+
+    let (mut accessible, mut gettable) = (0, 0);
+    for (kind, (before, after)) in kinds.iter().zip(before.iter().zip(&after)) {
+        if kind.threshold.is_none() {
+            println!("{}: {} fixed", kind.name, before.rolls);
+            continue;
+        }
+
+        let removed = before.rolls - after.rolls;
+        println!("{}: {} rolls, {} accessible, {} gettable", kind.name, before.rolls, before.accessible, removed);
+        accessible += before.accessible;
+        gettable += removed;
+    }
+
+    println!("Accessible: {}", accessible);
+    println!("Gettable: {}", gettable);
+}
+
+// Lists the groups of rolls that survive trimming, with rows and columns
+// counted from 0, and how many neighbors the survivors would have to lose.
+fn report_core(core: &Grid<Space>, rules: &Rules) {
+    let components = analysis::components(core, rules);
+    println!("Components: {}", components.len());
+    for (index, component) in components.iter().enumerate() {
+        println!(
+            "Component {}: {} rolls in rows {}-{}, columns {}-{}",
+            index + 1,
+            component.size,
+            component.top,
+            component.bottom,
+            component.left,
+            component.right
+        );
+    }
+
+    let mut needed = BTreeMap::new();
+    for position in core.positions() {
+        if let Space::Roll(_) = core[position] {
+            let removals = analysis::removals_to_free(core, rules, position);
+            match removals {
+                Some(removals) => println!("Roll {},{}: free after removing {}", position.0, position.1, removals),
+                None => println!("Roll {},{}: never free", position.0, position.1)
             }
+            *needed.entry(removals).or_insert(0) += 1;
+        }
+    }
+    for (removals, rolls) in needed {
+        match removals {
+            Some(removals) => println!("Free after removing {}: {}", removals, rolls),
+            None => println!("Never free: {}", rolls)
+        }
+    }
+}
 
-            if !is_bottom_edge(index, columns, board.len()) && !is_right_edge(index, columns) {
-                let neighbor = index + columns + 1;
-                if let Space::Roll(their_neighbors) = board[neighbor] {
-                    board[neighbor] = Space::Roll(their_neighbors - 1);
-                    todo.push(neighbor);
-                }
+// Applies `add <row> <column>` and `remove <row> <column>` lines, counting
+// rows and columns from 0, and prints the totals after each one. Rendering
+// shows the trimmed board once every update has been applied.
+fn run_updates(updates_file: &str, board: Grid<Space>, rules: Rules, render: bool) {
+    let (rows, columns) = (board.rows(), board.columns());
+    let mut warehouse = Warehouse::new(board, rules);
+    println!("Accessible: {}, Gettable: {}", warehouse.accessible(), warehouse.gettable());
+
+    let updates = fs::read_to_string(updates_file).unwrap();
+    for (index, line) in updates.lines().enumerate() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let position = match fields[..] {
+            [_, row, column] => row.parse().ok().zip(column.parse().ok()),
+            _ => None
+        };
+
+        let changed = match (fields.first(), position) {
+            (_, Some((row, column))) if row >= rows || column >= columns => None,
+            (Some(&"add"), Some(position)) => Some(warehouse.add_roll(position)),
+            (Some(&"remove"), Some(position)) => Some(warehouse.remove_roll(position)),
+            _ => None
+        };
+
+        match changed {
+            Some(true) => println!("{}: Accessible: {}, Gettable: {}", line, warehouse.accessible(), warehouse.gettable()),
+            Some(false) => println!("{}: unchanged", line),
+            None => {
+                eprintln!("{}:{}: expected add or remove with a row and column on the board", updates_file, index + 1);
+                process::exit(1);
             }
         }
     }
+
+    if render {
+        print!("{}", render::render_board(warehouse.core(), &warehouse.removed()));
+    }
 }
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    let file_name = &args[1];
+    let options = parse_args(&args);
+    let rules = &options.rules;
 
-    let file = fs::read_to_string(file_name).unwrap();
-    let (mut board, columns) = parse_board(&file);
+    let (mode, ignored) = ignored_flags(&options);
+    if !ignored.is_empty() {
+        eprintln!("{} can't be combined with {}", mode, ignored.join(", "));
+        process::exit(1);
+    }
 
-    let rolls = board.iter().fold(0, |acc, space| {
-        if let Space::Roll(_) = *space {
-            acc + 1
-        } else {
-            acc
+    if let Some((rows, columns)) = options.bench {
+        run_benchmark(rows, columns, rules);
+        return;
+    }
+
+    if options.bitboard {
+        run_bitboard(&options);
+        return;
+    }
+
+    if options.layers {
+        run_stack(&options);
+        return;
+    }
+
+    if let Some(legend_file) = &options.legend {
+        run_legend(&options, legend_file);
+        return;
+    }
+
+    let file = fs::read_to_string(&options.file_name).unwrap();
+    let mut board = match parse_board(&file, rules, options.validation) {
+        Ok(board) => board,
+        Err(errors) => {
+            for error in errors {
+                eprintln!("{}: {}", options.file_name, error);
+            }
+            process::exit(1);
         }
-    });
+    };
 
-    trim_board(&mut board, columns);
+    if let Some(updates_file) = &options.updates {
+        run_updates(updates_file, board, rules.clone(), options.render);
+        return;
+    }
 
-    let rolls_left = board.iter().fold(0, |acc, space| {
-        if let Space::Roll(_) = *space {
-            acc + 1
-        } else {
-            acc
+    let rolls = count_rolls(&board);
+    let accessible = accessible_rolls(&board, rules);
+
+    if options.rounds || options.render || options.heatmap.is_some() || options.frames.is_some() {
+        let rounds = trim_board_in_rounds(&mut board, rules);
+
+        if options.rounds {
+            for (index, removed) in rounds.removed_per_round.iter().enumerate() {
+                println!("Round {}: removed {}", index + 1, removed);
+            }
+            println!("Rounds: {}", rounds.removed_per_round.len());
+            print!("{}", render::round_lines(&rounds.removed_in));
         }
-    });
 
+        if options.render {
+            print!("{}", render::render_board(&board, &rounds.removed_in));
+        }
+
+        if let Some(heatmap_file) = &options.heatmap {
+            let image = render::heatmap(&board, &rounds.removed_in, rounds.removed_per_round.len());
+            let mut out = BufWriter::new(File::create(heatmap_file).unwrap());
+            render::write_ppm(&mut out, &image, IMAGE_SCALE).unwrap();
+        }
+
+        if let Some(frames_directory) = &options.frames {
+            render::write_frames(
+                Path::new(frames_directory),
+                &board,
+                &rounds.removed_in,
+                rounds.removed_per_round.len(),
+                IMAGE_SCALE
+            ).unwrap();
+        }
+    } else {
+        trim_board(&mut board, rules);
+    }
+
+    let rolls_left = count_rolls(&board);
+
+    if options.core {
+        report_core(&board, rules);
+    }
+
+    println!("Accessible: {}", accessible);
     println!("Gettable: {}", rolls - rolls_left);
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_support::example_board;
+    use crate::test_support::example_input;
+
+    #[test]
+    fn parse_example_board() {
+        let board = example_board(&Rules::default());
+
+        assert_eq!(board.rows(), 10);
+        assert_eq!(board.columns(), 10);
+        assert_eq!(count_rolls(&board), 71);
+        assert!(matches!(board[(0, 0)], Space::Empty));
+        assert!(matches!(board[(0, 2)], Space::Roll(3)));
+        assert!(matches!(board[(1, 0)], Space::Roll(3)));
+        assert!(matches!(board[(4, 4)], Space::Roll(8)));
+    }
+
+    #[test]
+    fn accessible_example_rolls() {
+        let board = example_board(&Rules::default());
+
+        assert_eq!(accessible_rolls(&board, &Rules::default()), 13);
+    }
+
+    #[test]
+    fn trim_example_board() {
+        let mut board = example_board(&Rules::default());
+        trim_board(&mut board, &Rules::default());
+
+        assert_eq!(count_rolls(&board), 71 - 43);
+    }
+
+    #[test]
+    fn trim_with_other_rules() {
+        let input = "@@@\n@@@\n@@@";
+
+        let rules = Rules::new(&Neighbourhood::VonNeumann, 3);
+        let mut board = parse_board(input, &rules, Validation::Strict).unwrap();
+        assert_eq!(accessible_rolls(&board, &rules), 4);
+        trim_board(&mut board, &rules);
+        assert_eq!(count_rolls(&board), 0);
+
+        let rules = Rules::new(&Neighbourhood::Moore, 8);
+        let mut board = parse_board(input, &rules, Validation::Strict).unwrap();
+        assert_eq!(accessible_rolls(&board, &rules), 8);
+        trim_board(&mut board, &rules);
+        assert_eq!(count_rolls(&board), 0);
+
+        let rules = Rules::new(&Neighbourhood::Radius(2), 5);
+        let mut board = parse_board("@@@@@\n@@@@@\n@@@@@\n@@@@@\n@@@@@", &rules, Validation::Strict).unwrap();
+        assert_eq!(accessible_rolls(&board, &rules), 0);
+        trim_board(&mut board, &rules);
+        assert_eq!(count_rolls(&board), 25);
+    }
+
+    #[test]
+    fn trim_with_lopsided_mask() {
+        // Each roll only looks at the cell to its right, so rolls are freed
+        // one at a time from the right-hand end of the row.
+        let rules = Rules::new(&Neighbourhood::from_mask("..#").unwrap(), 1);
+        let mut board = parse_board("@@@@.", &rules, Validation::Strict).unwrap();
+        assert_eq!(accessible_rolls(&board, &rules), 1);
+        trim_board(&mut board, &rules);
+        assert_eq!(count_rolls(&board), 0);
+    }
+
+    #[test]
+    fn parse_rule_args() {
+        let args: Vec<String> = ["day4", "input", "--threshold", "3", "--neighbourhood", "radius:2"]
+            .iter()
+            .map(|arg| arg.to_string())
+            .collect();
+
+        assert_eq!(
+            parse_args(&args),
+            Options {
+                file_name: String::from("input"),
+                rules: Rules::new(&Neighbourhood::Radius(2), 3),
+                neighbourhood: Neighbourhood::Radius(2),
+                validation: Validation::Strict,
+                rounds: false,
+                render: false,
+                heatmap: None,
+                frames: None,
+                bitboard: false,
+                bench: None,
+                updates: None,
+                layers: false,
+                legend: None,
+                core: false
+            }
+        );
+        assert_eq!(parse_neighbourhood("von-neumann"), Neighbourhood::VonNeumann);
+    }
+
+    #[test]
+    fn reject_ignored_flags() {
+        let flags = |given: &[&str]| {
+            let args: Vec<String> = given.iter().map(|arg| arg.to_string()).collect();
+            ignored_flags(&parse_args(&args))
+        };
 
+        assert_eq!(flags(&["day4", "input", "--rounds", "--render", "--core"]), ("", vec![]));
+        assert_eq!(flags(&["day4", "input", "--bitboard", "--rounds"]), ("--bitboard", vec![]));
+        assert_eq!(flags(&["day4", "input", "--bitboard", "--lenient"]), ("--bitboard", vec!["--lenient"]));
+        assert_eq!(
+            flags(&["day4", "input", "--layers", "--rounds", "--render"]),
+            ("--layers", vec!["--rounds", "--render"])
+        );
+        assert_eq!(
+            flags(&["day4", "input", "--legend", "legend", "--updates", "updates", "--core"]),
+            ("--legend", vec!["--updates", "--core"])
+        );
+        assert_eq!(flags(&["day4", "input", "--updates", "updates", "--render"]), ("--updates", vec![]));
+    }
+
+    #[test]
+    fn trim_example_in_rounds() {
+        let rules = Rules::default();
+        let mut board = example_board(&rules);
+        let rounds = trim_board_in_rounds(&mut board, &rules);
+
+        assert_eq!(rounds.removed_per_round, vec![13, 12, 7, 5, 2, 1, 1, 1, 1]);
+        assert_eq!(count_rolls(&board), 71 - 43);
+
+        assert_eq!(rounds.removed_in[(0, 0)], None);
+        assert_eq!(rounds.removed_in[(0, 2)], Some(1));
+        assert_eq!(rounds.removed_in[(4, 4)], None);
+        assert_eq!(
+            rounds.removed_in.cells().iter().filter(|round| **round == Some(2)).count(),
+            12
+        );
+    }
+
+    #[test]
+    fn rounds_match_worklist_trim() {
+        let rules = Rules::default();
+        let input = fs::read_to_string("input").unwrap();
+
+        let mut board = parse_board(&input, &rules, Validation::Strict).unwrap();
+        trim_board(&mut board, &rules);
+
+        let mut round_board = parse_board(&input, &rules, Validation::Strict).unwrap();
+        let rounds = trim_board_in_rounds(&mut round_board, &rules);
+
+        assert_eq!(count_rolls(&round_board), count_rolls(&board));
+        assert_eq!(rounds.removed_per_round[0], accessible_rolls(&parse_board(&input, &rules, Validation::Strict).unwrap(), &rules));
+    }
+
+    #[test]
+    fn trim_with_boundaries() {
+        let input = "@.@\n...\n@.@";
+        let mut rules = Rules::new(&Neighbourhood::Moore, 3);
+
+        let board = parse_board(input, &rules, Validation::Strict).unwrap();
+        assert_eq!(accessible_rolls(&board, &rules), 4);
+
+        rules.boundary = Boundary::Torus;
+        let board = parse_board(input, &rules, Validation::Strict).unwrap();
+        assert!(matches!(board[(0, 0)], Space::Roll(3)));
+        assert_eq!(accessible_rolls(&board, &rules), 0);
+
+        rules.boundary = Boundary::Padded;
+        let board = parse_board(input, &rules, Validation::Strict).unwrap();
+        assert!(matches!(board[(0, 0)], Space::Roll(5)));
+        assert_eq!(accessible_rolls(&board, &rules), 0);
+    }
+
+    #[test]
+    fn torus_narrower_than_neighbourhood() {
+        let rules = Rules { boundary: Boundary::Torus, ..Default::default() };
+
+        let mut board = parse_board("@", &rules, Validation::Strict).unwrap();
+        assert!(matches!(board[(0, 0)], Space::Roll(0)));
+        trim_board(&mut board, &rules);
+        assert_eq!(count_rolls(&board), 0);
 
-}
\ No newline at end of file
+        let board = parse_board("@.", &rules, Validation::Strict).unwrap();
+        assert!(matches!(board[(0, 0)], Space::Roll(0)));
+
+        let mut board = parse_board("@@", &rules, Validation::Strict).unwrap();
+        assert!(matches!(board[(0, 1)], Space::Roll(1)));
+        take_roll(&mut board, &rules, (0, 1));
+        assert!(matches!(board[(0, 0)], Space::Roll(0)));
+        place_roll(&mut board, &rules, (0, 1));
+        assert_eq!(board.cells(), &[Space::Roll(1), Space::Roll(1)]);
+        assert_eq!(rules.counted_by(&board, (0, 0)), vec![(0, 1)]);
+    }
+
+    #[test]
+    fn trim_example_with_boundaries() {
+        let input = example_input();
+
+        for boundary in [Boundary::Wall, Boundary::Torus, Boundary::Padded] {
+            let rules = Rules { boundary, ..Default::default() };
+
+            let mut board = parse_board(&input, &rules, Validation::Strict).unwrap();
+            trim_board(&mut board, &rules);
+
+            let mut round_board = parse_board(&input, &rules, Validation::Strict).unwrap();
+            trim_board_in_rounds(&mut round_board, &rules);
+
+            assert_eq!(count_rolls(&board), count_rolls(&round_board));
+            assert!(board.cells().iter().all(|space| !is_accessible(space, &rules)));
+        }
+
+        let rules = Rules { boundary: Boundary::Padded, ..Default::default() };
+        let mut board = parse_board(&input, &rules, Validation::Strict).unwrap();
+        trim_board(&mut board, &rules);
+        assert_eq!(count_rolls(&board), 69);
+    }
+
+    #[test]
+    fn validate_boards() {
+        let rules = Rules::default();
+
+        assert_eq!(
+            read_rows("@.@\n@@\n@?@@\n", Validation::Strict).err(),
+            Some(vec![
+                BoardError::Ragged { line: 2, expected: 3, found: 2 },
+                BoardError::Ragged { line: 3, expected: 3, found: 4 },
+                BoardError::UnknownCharacter { line: 3, column: 2, found: '?' }
+            ])
+        );
+        assert_eq!(read_rows("\n\n", Validation::Strict).err(), Some(vec![BoardError::Empty]));
+        assert_eq!(
+            read_rows("\n@@\n\n@@\n", Validation::Strict).err(),
+            Some(vec![BoardError::Ragged { line: 3, expected: 2, found: 0 }])
+        );
+
+        let board = parse_board("\n@.@\r\n@@\n@\n\n", &rules, Validation::Lenient).unwrap();
+        assert_eq!(board.rows(), 3);
+        assert_eq!(board.columns(), 3);
+        assert!(matches!(board[(1, 1)], Space::Roll(4)));
+        assert!(matches!(board[(1, 2)], Space::Empty));
+        assert!(matches!(board[(2, 2)], Space::Empty));
+
+        assert!(parse_board("@?", &rules, Validation::Lenient).is_err());
+    }
+}
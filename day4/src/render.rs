@@ -0,0 +1,234 @@
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::io::Write;
+use std::path::Path;
+
+use grid::Grid;
+
+use crate::Space;
+
+pub type Colour = [u8; 3];
+
+const EMPTY_COLOUR: Colour = [0, 0, 0];
+const SURVIVOR_COLOUR: Colour = [255, 255, 255];
+const FIRST_ROUND_COLOUR: Colour = [255, 220, 0];
+const LAST_ROUND_COLOUR: Colour = [120, 0, 160];
+const REMOVING_COLOUR: Colour = [230, 40, 40];
+const REMOVED_COLOUR: Colour = [60, 60, 60];
+
+// Draws a board with the input's notation, marking rolls that were removed
+// with `x`.
+pub fn render_board(board: &Grid<Space>, removed_in: &Grid<Option<usize>>) -> String {
+    let mut output = String::with_capacity(board.len() + board.rows());
+
+    for (position, space) in board.iter() {
+        output.push(match (space, removed_in[position]) {
+            (Space::Roll(_), _) => '@',
+            (Space::Empty, Some(_)) => 'x',
+            (Space::Empty, None) => '.'
+        });
+
+        if position.1 == board.columns() - 1 {
+            output.push('\n');
+        }
+    }
+
+    return output;
+}
+
+// Lists each removed roll as `row,column: round`, in board order.
+pub fn round_lines(removed_in: &Grid<Option<usize>>) -> String {
+    let mut output = String::new();
+
+    for (position, round) in removed_in.iter() {
+        if let Some(round) = round {
+            output.push_str(&format!("{},{}: {}\n", position.0, position.1, round));
+        }
+    }
+
+    return output;
+}
+
+// Blends from the first round's colour to the last round's colour.
+pub fn round_colour(round: usize, rounds: usize) -> Colour {
+    let fraction = if rounds > 1 {
+        (round - 1) as f64 / (rounds - 1) as f64
+    } else {
+        0.0
+    };
+
+    let mut colour = [0; 3];
+    for channel in 0..3 {
+        let first = FIRST_ROUND_COLOUR[channel] as f64;
+        let last = LAST_ROUND_COLOUR[channel] as f64;
+        colour[channel] = (first + (last - first) * fraction).round() as u8;
+    }
+
+    return colour;
+}
+
+// Colours removed rolls by the round they were removed in. Rolls that were
+// never removed are white and empty floor is black.
+pub fn heatmap(board: &Grid<Space>, removed_in: &Grid<Option<usize>>, rounds: usize) -> Grid<Colour> {
+    let cells = board
+        .iter()
+        .map(|(position, space)| match (space, removed_in[position]) {
+            (Space::Roll(_), _) => SURVIVOR_COLOUR,
+            (Space::Empty, Some(round)) => round_colour(round, rounds),
+            (Space::Empty, None) => EMPTY_COLOUR
+        })
+        .collect();
+
+    return Grid::new(board.rows(), board.columns(), cells);
+}
+
+// Shows the board as it stands during the given round: rolls being removed in
+// that round are red and rolls removed in earlier rounds are grey. Round 0 is
+// the board before anything is removed.
+pub fn frame(board: &Grid<Space>, removed_in: &Grid<Option<usize>>, round: usize) -> Grid<Colour> {
+    let cells = board
+        .iter()
+        .map(|(position, space)| match (space, removed_in[position]) {
+            (Space::Roll(_), _) => SURVIVOR_COLOUR,
+            (Space::Empty, Some(removed)) if removed < round => REMOVED_COLOUR,
+            (Space::Empty, Some(removed)) if removed == round => REMOVING_COLOUR,
+            (Space::Empty, Some(_)) => SURVIVOR_COLOUR,
+            (Space::Empty, None) => EMPTY_COLOUR
+        })
+        .collect();
+
+    return Grid::new(board.rows(), board.columns(), cells);
+}
+
+// Writes frame_<round>.ppm for rounds 0 through `rounds` into the directory.
+// The numbered frames can be stitched into a GIF or video with e.g. ffmpeg.
+pub fn write_frames(
+    directory: &Path,
+    board: &Grid<Space>,
+    removed_in: &Grid<Option<usize>>,
+    rounds: usize,
+    scale: usize
+) -> io::Result<()> {
+    fs::create_dir_all(directory)?;
+
+    let width = rounds.to_string().len();
+    for round in 0..=rounds {
+        let path = directory.join(format!("frame_{:0width$}.ppm", round, width = width));
+        let mut out = BufWriter::new(File::create(path)?);
+        write_ppm(&mut out, &frame(board, removed_in, round), scale)?;
+    }
+
+    return Ok(());
+}
+
+// Writes a binary PPM, drawing each cell as a `scale` by `scale` square.
+pub fn write_ppm<W: Write>(out: &mut W, image: &Grid<Colour>, scale: usize) -> io::Result<()> {
+    write!(out, "P6\n{} {}\n255\n", image.columns() * scale, image.rows() * scale)?;
+
+    let mut line = Vec::with_capacity(image.columns() * scale * 3);
+    for row in 0..image.rows() {
+        line.clear();
+        for column in 0..image.columns() {
+            for _ in 0..scale {
+                line.extend_from_slice(&image[(row, column)]);
+            }
+        }
+
+        for _ in 0..scale {
+            out.write_all(&line)?;
+        }
+    }
+
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn removed_board() -> (Grid<Space>, Grid<Option<usize>>) {
+        let board = Grid::new(2, 3, vec![
+            Space::Roll(0), Space::Empty, Space::Empty,
+            Space::Empty, Space::Empty, Space::Roll(1)
+        ]);
+        let removed_in = Grid::new(2, 3, vec![
+            None, Some(1), None,
+            Some(2), None, None
+        ]);
+        (board, removed_in)
+    }
+
+    #[test]
+    fn render_removed_rolls() {
+        let (board, removed_in) = removed_board();
+
+        assert_eq!(render_board(&board, &removed_in), "@x.\nx.@\n");
+    }
+
+    #[test]
+    fn list_removal_rounds() {
+        let (_, removed_in) = removed_board();
+
+        assert_eq!(round_lines(&removed_in), "0,1: 1\n1,0: 2\n");
+    }
+
+    #[test]
+    fn colour_by_round() {
+        assert_eq!(round_colour(1, 5), FIRST_ROUND_COLOUR);
+        assert_eq!(round_colour(5, 5), LAST_ROUND_COLOUR);
+        assert_eq!(round_colour(3, 5), [188, 110, 80]);
+        assert_eq!(round_colour(1, 1), FIRST_ROUND_COLOUR);
+
+        let (board, removed_in) = removed_board();
+        assert_eq!(
+            heatmap(&board, &removed_in, 2).cells(),
+            &[
+                SURVIVOR_COLOUR, FIRST_ROUND_COLOUR, EMPTY_COLOUR,
+                LAST_ROUND_COLOUR, EMPTY_COLOUR, SURVIVOR_COLOUR
+            ]
+        );
+    }
+
+    #[test]
+    fn scaled_ppm() {
+        let image = Grid::new(1, 2, vec![[1, 2, 3], [4, 5, 6]]);
+        let mut out = Vec::new();
+        write_ppm(&mut out, &image, 2).unwrap();
+
+        let header = b"P6\n4 2\n255\n";
+        assert_eq!(&out[..header.len()], header);
+        assert_eq!(
+            &out[header.len()..],
+            &[1, 2, 3, 1, 2, 3, 4, 5, 6, 4, 5, 6, 1, 2, 3, 1, 2, 3, 4, 5, 6, 4, 5, 6]
+        );
+    }
+
+    #[test]
+    fn frames_by_round() {
+        let (board, removed_in) = removed_board();
+
+        assert_eq!(
+            frame(&board, &removed_in, 0).cells(),
+            &[
+                SURVIVOR_COLOUR, SURVIVOR_COLOUR, EMPTY_COLOUR,
+                SURVIVOR_COLOUR, EMPTY_COLOUR, SURVIVOR_COLOUR
+            ]
+        );
+        assert_eq!(
+            frame(&board, &removed_in, 1).cells(),
+            &[
+                SURVIVOR_COLOUR, REMOVING_COLOUR, EMPTY_COLOUR,
+                SURVIVOR_COLOUR, EMPTY_COLOUR, SURVIVOR_COLOUR
+            ]
+        );
+        assert_eq!(
+            frame(&board, &removed_in, 2).cells(),
+            &[
+                SURVIVOR_COLOUR, REMOVED_COLOUR, EMPTY_COLOUR,
+                REMOVING_COLOUR, EMPTY_COLOUR, SURVIVOR_COLOUR
+            ]
+        );
+    }
+}
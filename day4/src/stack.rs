@@ -0,0 +1,391 @@
+use grid::Neighbourhood;
+
+use crate::read_cell;
+use crate::BoardError;
+use crate::Space;
+use crate::Validation;
+
+// Rolls stacked in any number of dimensions. The shape lists the outermost
+// axis first and ends with rows and columns, so a flat board is a stack with
+// two dimensions. Cells are stored with the last axis varying fastest.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Stack {
+    shape: Vec<usize>,
+    strides: Vec<usize>,
+    cells: Vec<Space>
+}
+
+// Which cells count as neighbors in a stack, as offsets with one entry per
+// axis, and how many of them keep a roll out of reach.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StackRules {
+    threshold: u32,
+    offsets: Vec<Vec<isize>>,
+    reverse_offsets: Vec<Vec<isize>>
+}
+
+impl StackRules {
+    pub fn new(offsets: Vec<Vec<isize>>, threshold: u32) -> StackRules {
+        let reverse_offsets = offsets
+            .iter()
+            .map(|offset| offset.iter().map(|step| -step).collect())
+            .collect();
+        StackRules { threshold, offsets, reverse_offsets }
+    }
+
+    // Extends a neighbourhood to the given number of dimensions: von Neumann
+    // steps along one axis at a time, while Moore and radius neighbourhoods
+    // take every cell of the surrounding cube, so Moore has 26 neighbors in
+    // 3D. Custom neighbourhoods are only drawn in 2D and have no extension.
+    pub fn from_neighbourhood(neighbourhood: &Neighbourhood, dimensions: usize, threshold: u32) -> Option<StackRules> {
+        let offsets = match neighbourhood {
+            Neighbourhood::VonNeumann => {
+                let mut offsets = Vec::new();
+                for axis in 0..dimensions {
+                    for step in [-1, 1] {
+                        let mut offset = vec![0; dimensions];
+                        offset[axis] = step;
+                        offsets.push(offset);
+                    }
+                }
+                offsets
+            },
+            Neighbourhood::Moore => cube_offsets(dimensions, 1),
+            Neighbourhood::Radius(radius) => cube_offsets(dimensions, *radius as isize),
+            Neighbourhood::Custom(_) => return None
+        };
+
+        return Some(StackRules::new(offsets, threshold));
+    }
+
+    fn is_accessible(&self, space: &Space) -> bool {
+        matches!(*space, Space::Roll(neighbors) if neighbors < self.threshold)
+    }
+}
+
+// Every offset with each step between -radius and radius, except staying put.
+fn cube_offsets(dimensions: usize, radius: isize) -> Vec<Vec<isize>> {
+    let mut offsets = Vec::new();
+    let mut offset = vec![-radius; dimensions];
+
+    loop {
+        if offset.iter().any(|step| *step != 0) {
+            offsets.push(offset.clone());
+        }
+
+        // Count up like an odometer, from the last axis.
+        let Some(axis) = offset.iter().rposition(|step| *step < radius) else {
+            return offsets;
+        };
+        offset[axis] += 1;
+        for step in &mut offset[axis + 1..] {
+            *step = -radius;
+        }
+    }
+}
+
+impl Stack {
+    pub fn new(shape: Vec<usize>, cells: Vec<Space>) -> Stack {
+        assert_eq!(shape.iter().product::<usize>(), cells.len(), "the cells don't fill the shape");
+
+        let mut strides = vec![1; shape.len()];
+        for axis in (0..shape.len().saturating_sub(1)).rev() {
+            strides[axis] = strides[axis + 1] * shape[axis + 1];
+        }
+
+        Stack { shape, strides, cells }
+    }
+
+    // Reads layers separated by blank lines. The longest run of blank lines
+    // sets the number of dimensions: one blank line between layers gives a
+    // 3D stack, and two blank lines between stacks of layers give 4D. Each
+    // group of layers has to be the same size as the others.
+    pub fn read(input: &str, validation: Validation) -> Result<Stack, Vec<BoardError>> {
+        let lines: Vec<(usize, &str)> = input
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line.trim_end()))
+            .collect();
+
+        let mut layers: Vec<Vec<(usize, &str)>> = Vec::new();
+        // The line each layer after the first starts on and the number of
+        // blank lines before it.
+        let mut separators: Vec<(usize, usize)> = Vec::new();
+        let mut blank = 0;
+        for (line_number, line) in lines {
+            if line.is_empty() {
+                blank += 1;
+                continue;
+            }
+
+            if layers.is_empty() || blank > 0 {
+                if !layers.is_empty() {
+                    separators.push((line_number, blank));
+                }
+                layers.push(Vec::new());
+            }
+            blank = 0;
+            layers.last_mut().unwrap().push((line_number, line));
+        }
+
+        if layers.is_empty() {
+            return Err(vec![BoardError::Empty]);
+        }
+
+        let mut errors = Vec::new();
+        let outer_shape = outer_shape(&layers, &separators, &mut errors);
+
+        let rows_in = layers.iter().map(|layer| layer.len());
+        let rows = match validation {
+            Validation::Strict => layers[0].len(),
+            Validation::Lenient => rows_in.max().unwrap_or(0)
+        };
+        let lengths = layers.iter().flatten().map(|(_, line)| line.chars().count());
+        let columns = match validation {
+            Validation::Strict => layers[0][0].1.chars().count(),
+            Validation::Lenient => lengths.max().unwrap_or(0)
+        };
+
+        let mut cells = Vec::with_capacity(layers.len() * rows * columns);
+        for layer in &layers {
+            if layer.len() != rows && validation == Validation::Strict {
+                errors.push(BoardError::LayerRows { line: layer[0].0, expected: rows, found: layer.len() });
+            }
+
+            for (line_number, line) in layer {
+                let found = line.chars().count();
+                if found != columns && validation == Validation::Strict {
+                    errors.push(BoardError::Ragged { line: *line_number, expected: columns, found });
+                }
+
+                for (index, current) in line.chars().enumerate() {
+                    cells.push(read_cell(current, *line_number, index + 1, &mut errors));
+                }
+                for _ in found..columns {
+                    cells.push(Space::Empty);
+                }
+            }
+
+            for _ in layer.len()..rows {
+                cells.extend((0..columns).map(|_| Space::Empty));
+            }
+        }
+
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+
+        let mut shape = outer_shape;
+        shape.extend([rows, columns]);
+        return Ok(Stack::new(shape, cells));
+    }
+
+    pub fn shape(&self) -> &[usize] {
+        &self.shape
+    }
+
+    pub fn dimensions(&self) -> usize {
+        self.shape.len()
+    }
+
+    // The cells a cell reaches with the given offsets, as indices into the
+    // cells.
+    fn neighbors(&self, index: usize, offsets: &[Vec<isize>]) -> Vec<usize> {
+        let coordinates: Vec<usize> = self.strides
+            .iter()
+            .zip(&self.shape)
+            .map(|(stride, size)| index / stride % size)
+            .collect();
+
+        let mut neighbors = Vec::with_capacity(offsets.len());
+        'offsets: for offset in offsets {
+            let mut neighbor = 0;
+            for axis in 0..self.shape.len() {
+                match coordinates[axis].checked_add_signed(offset[axis]) {
+                    Some(coordinate) if coordinate < self.shape[axis] => {
+                        neighbor += coordinate * self.strides[axis];
+                    },
+                    _ => continue 'offsets
+                }
+            }
+            neighbors.push(neighbor);
+        }
+
+        return neighbors;
+    }
+
+    // Stores each roll's neighbor count, as parsing does for a flat board.
+    pub fn count_neighbors(&mut self, rules: &StackRules) {
+        for index in 0..self.cells.len() {
+            if let Space::Empty = self.cells[index] {
+                continue;
+            }
+
+            let neighbors = self
+                .neighbors(index, &rules.offsets)
+                .iter()
+                .filter(|neighbor| matches!(self.cells[**neighbor], Space::Roll(_)))
+                .count();
+            self.cells[index] = Space::Roll(neighbors as u32);
+        }
+    }
+
+    pub fn count_rolls(&self) -> usize {
+        self.cells.iter().filter(|space| matches!(space, Space::Roll(_))).count()
+    }
+
+    pub fn accessible_rolls(&self, rules: &StackRules) -> usize {
+        self.cells.iter().filter(|space| rules.is_accessible(space)).count()
+    }
+
+    // Removes accessible rolls until none are left, as trim_board does.
+    pub fn trim(&mut self, rules: &StackRules) {
+        let mut todo: Vec<usize> = (0..self.cells.len()).collect();
+
+        while let Some(index) = todo.pop() {
+            if !rules.is_accessible(&self.cells[index]) {
+                continue;
+            }
+
+            self.cells[index] = Space::Empty;
+            for neighbor in self.neighbors(index, &rules.reverse_offsets) {
+                if let Space::Roll(their_neighbors) = self.cells[neighbor] {
+                    self.cells[neighbor] = Space::Roll(their_neighbors - 1);
+                    todo.push(neighbor);
+                }
+            }
+        }
+    }
+}
+
+// Works out the sizes of the axes above rows and columns from the runs of
+// blank lines between layers, outermost first. A layer that starts a new
+// group at some level has to be preceded by one more blank line than a layer
+// that starts a new group one level down.
+fn outer_shape(layers: &[Vec<(usize, &str)>], separators: &[(usize, usize)], errors: &mut Vec<BoardError>) -> Vec<usize> {
+    let levels = separators.iter().map(|(_, blank)| *blank).max().unwrap_or(0);
+
+    // spans[level] is how many layers make up a group at that level, so a
+    // group at level 0 is a single layer and the top level is everything.
+    let mut spans = vec![1];
+    for level in 1..=levels {
+        let span = separators
+            .iter()
+            .position(|(_, blank)| *blank > level)
+            .map_or(layers.len(), |index| index + 1);
+        spans.push(span);
+    }
+
+    for (index, (line, found)) in separators.iter().enumerate() {
+        let layer = index + 1;
+        let expected = spans[..levels].iter().filter(|span| layer % **span == 0).count();
+        if *found != expected {
+            errors.push(BoardError::Uneven { line: *line, expected, found: *found });
+        }
+    }
+
+    if spans.windows(2).any(|pair| pair[1] % pair[0] != 0) {
+        let last = layers.last().unwrap();
+        errors.push(BoardError::ShortGroup { line: last[last.len() - 1].0 });
+        return vec![layers.len()];
+    }
+
+    let mut shape: Vec<usize> = spans.windows(2).map(|pair| pair[1] / pair[0]).collect();
+    shape.reverse();
+    return shape;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitboard::BitBoard;
+    use crate::count_rolls;
+    use crate::parse_board;
+    use crate::test_support::example_input;
+    use crate::trim_board;
+    use crate::Rules;
+
+    #[test]
+    fn neighbourhood_sizes() {
+        let count = |neighbourhood, dimensions| {
+            StackRules::from_neighbourhood(&neighbourhood, dimensions, 4).unwrap().offsets.len()
+        };
+
+        assert_eq!(count(Neighbourhood::Moore, 2), 8);
+        assert_eq!(count(Neighbourhood::Moore, 3), 26);
+        assert_eq!(count(Neighbourhood::Moore, 4), 80);
+        assert_eq!(count(Neighbourhood::VonNeumann, 3), 6);
+        assert_eq!(count(Neighbourhood::Radius(2), 3), 124);
+        assert_eq!(StackRules::from_neighbourhood(&Neighbourhood::Custom(vec![(0, 1)]), 3, 4), None);
+    }
+
+    #[test]
+    fn read_layers() {
+        let stack = Stack::read("@.\n.@\n\n..\n@@\n", Validation::Strict).unwrap();
+        assert_eq!(stack.shape(), &[2, 2, 2]);
+        assert_eq!(stack.cells.iter().filter(|space| matches!(space, Space::Roll(_))).count(), 4);
+
+        let stack = Stack::read("@\n\n@\n\n@\n\n\n@\n\n@\n\n@\n", Validation::Strict).unwrap();
+        assert_eq!(stack.shape(), &[2, 3, 1, 1]);
+
+        let stack = Stack::read("@@\n@\n\n@\n", Validation::Lenient).unwrap();
+        assert_eq!(stack.shape(), &[2, 2, 2]);
+    }
+
+    #[test]
+    fn read_uneven_layers() {
+        assert_eq!(
+            Stack::read("@@\n@@\n\n@@\n", Validation::Strict),
+            Err(vec![BoardError::LayerRows { line: 4, expected: 2, found: 1 }])
+        );
+        assert_eq!(
+            Stack::read("@\n\n@\n\n\n@\n\n\n@\n", Validation::Strict),
+            Err(vec![BoardError::Uneven { line: 9, expected: 1, found: 2 }])
+        );
+        assert_eq!(
+            Stack::read("@\n\n@\n\n\n@\n", Validation::Strict),
+            Err(vec![BoardError::ShortGroup { line: 6 }])
+        );
+    }
+
+    #[test]
+    fn trim_cube() {
+        // A full 3x3x3 cube: corners have 7 neighbors, edges 11, faces 17.
+        let layer = "@@@\n@@@\n@@@\n";
+        let mut stack = Stack::read(&[layer; 3].join("\n"), Validation::Strict).unwrap();
+        let rules = StackRules::from_neighbourhood(&Neighbourhood::Moore, 3, 12).unwrap();
+        stack.count_neighbors(&rules);
+
+        assert_eq!(stack.cells[0], Space::Roll(7));
+        assert_eq!(stack.cells[13], Space::Roll(26));
+        assert_eq!(stack.accessible_rolls(&rules), 20);
+
+        stack.trim(&rules);
+        assert_eq!(stack.count_rolls(), 0);
+    }
+
+    #[test]
+    fn flat_stack_matches_grid() {
+        let mut boards = vec![example_input()];
+        boards.push(BitBoard::random(30, 40, 0.6, 7).to_text());
+
+        for text in boards {
+            for neighbourhood in [Neighbourhood::Moore, Neighbourhood::VonNeumann, Neighbourhood::Radius(2)] {
+                for threshold in [2, 4, 10] {
+                    let rules = Rules::new(&neighbourhood, threshold);
+                    let mut board = parse_board(&text, &rules, Validation::Strict).unwrap();
+
+                    let stack_rules = StackRules::from_neighbourhood(&neighbourhood, 2, threshold).unwrap();
+                    let mut stack = Stack::read(&text, Validation::Strict).unwrap();
+                    stack.count_neighbors(&stack_rules);
+
+                    assert_eq!(stack.cells, board.cells());
+                    trim_board(&mut board, &rules);
+                    stack.trim(&stack_rules);
+                    assert_eq!(stack.count_rolls(), count_rolls(&board));
+                    assert_eq!(stack.cells, board.cells());
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,18 @@
+// Helpers shared by the tests of several modules.
+
+use std::fs;
+
+use grid::Grid;
+
+use crate::parse_board;
+use crate::Rules;
+use crate::Space;
+use crate::Validation;
+
+pub fn example_input() -> String {
+    fs::read_to_string("example_input").unwrap()
+}
+
+pub fn example_board(rules: &Rules) -> Grid<Space> {
+    parse_board(&example_input(), rules, Validation::Strict).unwrap()
+}
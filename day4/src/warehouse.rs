@@ -0,0 +1,295 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+use grid::Grid;
+
+use crate::count_neighbors;
+use crate::is_accessible;
+use crate::place_roll;
+use crate::take_roll;
+use crate::trim_from;
+use crate::Rules;
+use crate::Space;
+
+// A board that keeps its layout and its trimmed core up to date as single
+// rolls are placed or taken away, so a change only costs as much as the part
+// of the board it affects.
+pub struct Warehouse {
+    rules: Rules,
+    // Every roll with its neighbor count, as parsed.
+    layout: Grid<Space>,
+    // The rolls left after trimming, counting only each other.
+    core: Grid<Space>,
+    // When each trimmed roll was taken off the core, counting from 1, or 0
+    // for cells that hold no trimmed roll. A trimmed roll always has fewer
+    // neighbors than the threshold among the core and the rolls taken after
+    // it, which is what bounds the search when a roll is added.
+    trimmed_at: Grid<usize>,
+    trims: usize,
+    accessible: usize,
+    rolls: usize,
+    core_rolls: usize
+}
+
+impl Warehouse {
+    pub fn new(layout: Grid<Space>, rules: Rules) -> Warehouse {
+        let accessible = layout.cells().iter().filter(|space| is_accessible(space, &rules)).count();
+        let rolls = layout.cells().iter().filter(|space| matches!(space, Space::Roll(_))).count();
+
+        let trimmed_at = Grid::filled(layout.rows(), layout.columns(), 0);
+        let core = layout.clone();
+        let todo = core.positions().collect();
+
+        let mut warehouse = Warehouse { rules, layout, core, trimmed_at, trims: 0, accessible, rolls, core_rolls: rolls };
+        warehouse.trim(todo);
+        return warehouse;
+    }
+
+    // Rolls a forklift can reach before anything is removed.
+    pub fn accessible(&self) -> usize {
+        self.accessible
+    }
+
+    // Rolls that can be removed by trimming.
+    pub fn gettable(&self) -> usize {
+        self.rolls - self.core_rolls
+    }
+
+    pub fn core(&self) -> &Grid<Space> {
+        &self.core
+    }
+
+    // Marks the rolls that trimming removes. They are all reported as removed
+    // in the first round, since the rounds aren't tracked between updates.
+    pub fn removed(&self) -> Grid<Option<usize>> {
+        let cells = self.layout
+            .iter()
+            .map(|(position, space)| match (space, &self.core[position]) {
+                (Space::Roll(_), Space::Empty) => Some(1),
+                _ => None
+            })
+            .collect();
+
+        return Grid::new(self.layout.rows(), self.layout.columns(), cells);
+    }
+
+    // Recounts accessible rolls among those whose counts may have changed.
+    fn update_accessible<F>(&mut self, position: (usize, usize), change: F)
+    where
+        F: FnOnce(&mut Grid<Space>, &Rules) -> Vec<(usize, usize)>
+    {
+        let mut affected = self.rules.counted_by(&self.layout, position);
+        affected.push(position);
+        affected.sort();
+        affected.dedup();
+
+        let before = affected.iter().filter(|cell| is_accessible(&self.layout[**cell], &self.rules)).count();
+        change(&mut self.layout, &self.rules);
+        let after = affected.iter().filter(|cell| is_accessible(&self.layout[**cell], &self.rules)).count();
+
+        self.accessible = self.accessible + after - before;
+    }
+
+    // Places a roll, returning false if the cell already held one.
+    pub fn add_roll(&mut self, position: (usize, usize)) -> bool {
+        if let Space::Roll(_) = self.layout[position] {
+            return false;
+        }
+
+        self.update_accessible(position, |layout, rules| place_roll(layout, rules, position));
+        self.rolls += 1;
+
+        // Put back every trimmed roll that might join the core now, and trim
+        // them again.
+        let revived = self.revivable(position);
+        for cell in &revived {
+            self.core[*cell] = Space::Roll(0);
+            self.trimmed_at[*cell] = 0;
+        }
+        self.core_rolls += revived.len();
+
+        let mut recount = revived.clone();
+        for cell in &revived {
+            recount.extend(self.rules.counted_by(&self.core, *cell));
+        }
+        recount.sort();
+        recount.dedup();
+        for cell in recount {
+            if let Space::Roll(_) = self.core[cell] {
+                self.core[cell] = Space::Roll(count_neighbors(&self.core, &self.rules, cell));
+            }
+        }
+
+        self.trim(revived);
+        return true;
+    }
+
+    // Takes a roll away, returning false if the cell was already empty.
+    pub fn remove_roll(&mut self, position: (usize, usize)) -> bool {
+        if let Space::Empty = self.layout[position] {
+            return false;
+        }
+
+        self.update_accessible(position, |layout, rules| take_roll(layout, rules, position));
+        self.rolls -= 1;
+
+        // Taking rolls away can only shrink the core.
+        if let Space::Roll(_) = self.core[position] {
+            let touched = take_roll(&mut self.core, &self.rules, position);
+            self.core_rolls -= 1;
+            self.trim(touched);
+        } else {
+            self.trimmed_at[position] = 0;
+        }
+
+        return true;
+    }
+
+    // Trims the core from the given rolls, numbering the rolls it takes after
+    // every roll trimmed before.
+    fn trim(&mut self, todo: Vec<(usize, usize)>) {
+        let rules = &self.rules;
+        let removed = trim_from(&mut self.core, rules, todo, |space, _| is_accessible(space, rules));
+        for cell in &removed {
+            self.trims += 1;
+            self.trimmed_at[*cell] = self.trims;
+        }
+        self.core_rolls -= removed.len();
+    }
+
+    // How many neighbors a trimmed roll had when it was taken off the core.
+    fn support(&self, cell: (usize, usize)) -> u32 {
+        let neighbors = self.rules
+            .neighbors(&self.layout, cell)
+            .iter()
+            .filter(|next| match (&self.layout[**next], &self.core[**next]) {
+                (_, Space::Roll(_)) => true,
+                (Space::Roll(_), Space::Empty) => self.trimmed_at[**next] >= self.trimmed_at[cell],
+                (Space::Empty, _) => false
+            })
+            .count();
+
+        return neighbors as u32 + self.rules.border_neighbors(&self.layout, cell);
+    }
+
+    // The new roll and every trimmed roll that might join the core with it. A
+    // trimmed roll can only join if the new roll or rolls trimmed before it
+    // make up what it was short of, so the search stops at rolls that can't
+    // make the threshold even if every candidate joins. Trimming the
+    // candidates again leaves exactly the rolls that join.
+    fn revivable(&self, start: (usize, usize)) -> Vec<(usize, usize)> {
+        let mut seen = HashSet::from([start]);
+        let mut region = vec![start];
+        let mut todo = vec![start];
+        let mut gained: HashMap<(usize, usize), u32> = HashMap::new();
+
+        while let Some(cell) = todo.pop() {
+            for next in self.rules.counted_by(&self.layout, cell) {
+                let trimmed = matches!(self.layout[next], Space::Roll(_)) && matches!(self.core[next], Space::Empty);
+                if !trimmed || seen.contains(&next) {
+                    continue;
+                }
+                // A roll trimmed after this one was already counted in its
+                // support.
+                if cell != start && self.trimmed_at[cell] >= self.trimmed_at[next] {
+                    continue;
+                }
+
+                let gain = gained.entry(next).or_insert(0);
+                *gain += 1;
+                if self.support(next) + *gain >= self.rules.threshold {
+                    seen.insert(next);
+                    region.push(next);
+                    todo.push(next);
+                }
+            }
+        }
+
+        return region;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitboard::BitBoard;
+    use crate::bitboard::Random;
+    use crate::parse_board;
+    use crate::Boundary;
+    use crate::test_support::example_board;
+    use crate::Validation;
+    use grid::Neighbourhood;
+
+    fn rebuilt(warehouse: &Warehouse, rules: Rules) -> Warehouse {
+        let text = BitBoard::from_grid(&warehouse.layout).to_text();
+        Warehouse::new(parse_board(&text, &rules, Validation::Strict).unwrap(), rules)
+    }
+
+    #[test]
+    fn update_example() {
+        let rules = Rules::default();
+        let layout = example_board(&rules);
+        let mut warehouse = Warehouse::new(layout, Rules::default());
+
+        assert_eq!(warehouse.accessible(), 13);
+        assert_eq!(warehouse.gettable(), 43);
+
+        assert!(!warehouse.add_roll((0, 2)));
+        assert!(!warehouse.remove_roll((0, 0)));
+
+        assert!(warehouse.remove_roll((4, 4)));
+        assert!(warehouse.add_roll((4, 4)));
+        assert_eq!(warehouse.accessible(), 13);
+        assert_eq!(warehouse.gettable(), 43);
+        assert_eq!(warehouse.core(), rebuilt(&warehouse, Rules::default()).core());
+    }
+
+    #[test]
+    fn revive_near_the_new_roll() {
+        let rules = Rules::default();
+        let layout = example_board(&rules);
+        let mut warehouse = Warehouse::new(layout, Rules::default());
+
+        // Most of the board is trimmed, but only the rolls around the corner
+        // could join the core again.
+        place_roll(&mut warehouse.layout, &rules, (0, 0));
+        assert_eq!(warehouse.revivable((0, 0)).len(), 9);
+        take_roll(&mut warehouse.layout, &rules, (0, 0));
+
+        assert!(warehouse.add_roll((0, 0)));
+        assert_eq!(warehouse.core(), rebuilt(&warehouse, Rules::default()).core());
+        assert_eq!(warehouse.gettable(), rebuilt(&warehouse, Rules::default()).gettable());
+    }
+
+    #[test]
+    fn updates_match_rebuilding() {
+        let setups = [
+            (Neighbourhood::Moore, Boundary::Wall, 4),
+            (Neighbourhood::VonNeumann, Boundary::Torus, 2),
+            (Neighbourhood::Radius(2), Boundary::Padded, 12),
+            (Neighbourhood::Custom(vec![(0, 1), (1, 1), (2, 0)]), Boundary::Wall, 2)
+        ];
+
+        for (neighbourhood, boundary, threshold) in setups {
+            let make_rules = || Rules { boundary, ..Rules::new(&neighbourhood, threshold) };
+            let layout = parse_board(&BitBoard::random(12, 12, 0.6, 3).to_text(), &make_rules(), Validation::Strict).unwrap();
+            let mut warehouse = Warehouse::new(layout, make_rules());
+            let mut random = Random::new(threshold as u64);
+
+            for _ in 0..300 {
+                let position = ((random.next_word() % 12) as usize, (random.next_word() % 12) as usize);
+                if random.next_word().is_multiple_of(2) {
+                    warehouse.add_roll(position);
+                } else {
+                    warehouse.remove_roll(position);
+                }
+
+                let expected = rebuilt(&warehouse, make_rules());
+                assert_eq!(warehouse.accessible(), expected.accessible());
+                assert_eq!(warehouse.gettable(), expected.gettable());
+                assert_eq!(warehouse.core(), expected.core());
+                assert_eq!(warehouse.layout, expected.layout);
+            }
+        }
+    }
+}
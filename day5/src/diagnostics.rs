@@ -0,0 +1,153 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::provenance::SourceRange;
+
+// Something in the range section worth cleaning up. Overlaps are reported on
+// the later of the two lines and containment on the line of the inner range.
+#[derive(Debug, PartialEq)]
+pub enum Diagnostic {
+    // The lower end is above the upper end, so the range covers nothing.
+    Inverted(SourceRange),
+    // The same range as an earlier line.
+    Duplicate { source: SourceRange, first_line: usize },
+    // Every ID is already covered by one other range.
+    Contained { source: SourceRange, container: SourceRange },
+    // The ranges share some IDs but neither covers the other.
+    Overlap { source: SourceRange, other: SourceRange }
+}
+
+impl Diagnostic {
+    pub fn line(&self) -> usize {
+        match self {
+            Diagnostic::Inverted(source) => source.line,
+            Diagnostic::Duplicate { source, .. } => source.line,
+            Diagnostic::Contained { source, .. } => source.line,
+            Diagnostic::Overlap { source, .. } => source.line
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: ", self.line())?;
+        match self {
+            Diagnostic::Inverted(source) => write!(f, "{} is inverted", source.range),
+            Diagnostic::Duplicate { source, first_line } => {
+                write!(f, "{} duplicates line {}", source.range, first_line)
+            },
+            Diagnostic::Contained { source, container } => {
+                write!(f, "{} is inside {}", source.range, container)
+            },
+            Diagnostic::Overlap { source, other } => write!(f, "{} overlaps {}", source.range, other)
+        }
+    }
+}
+
+// Checks the ranges against each other, in line order. Duplicates are only
+// compared with other ranges through their first occurrence, and a range
+// inside several others is reported against the one that starts first.
+pub fn diagnose(sources: &[SourceRange]) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let mut first_lines = HashMap::new();
+    let mut unique = Vec::new();
+
+    for source in sources {
+        if source.range.0 > source.range.1 {
+            diagnostics.push(Diagnostic::Inverted(*source));
+            continue;
+        }
+
+        match first_lines.get(&(source.range.0, source.range.1)) {
+            Some(first_line) => diagnostics.push(Diagnostic::Duplicate { source: *source, first_line: *first_line }),
+            None => {
+                first_lines.insert((source.range.0, source.range.1), source.line);
+                unique.push(*source);
+            }
+        }
+    }
+
+    // Sorted by lower end and then widest first, a range can only contain or
+    // overlap the ranges after it that start before it ends.
+    unique.sort_by_key(|source| (source.range.0, Reverse(source.range.1)));
+    let mut contained = vec![false; unique.len()];
+
+    for first in 0..unique.len() {
+        let outer = unique[first];
+        for second in first + 1..unique.len() {
+            let inner = unique[second];
+            if inner.range.0 > outer.range.1 {
+                break;
+            }
+
+            let (earlier, later) = if outer.line < inner.line { (outer, inner) } else { (inner, outer) };
+            if inner.range.1 > outer.range.1 {
+                diagnostics.push(Diagnostic::Overlap { source: later, other: earlier });
+            } else if !contained[second] {
+                contained[second] = true;
+                diagnostics.push(Diagnostic::Contained { source: inner, container: outer });
+            }
+        }
+    }
+
+    diagnostics.sort_by_key(|diagnostic| diagnostic.line());
+    return diagnostics;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::source;
+
+    #[test]
+    fn diagnose_ranges() {
+        let sources = vec![
+            source(1, 1, 10),
+            source(2, 20, 30),
+            source(3, 3, 4),
+            source(4, 25, 40),
+            source(5, 9, 2),
+            source(6, 1, 10),
+            source(7, 50, 60),
+            source(8, 1, 10)
+        ];
+
+        assert_eq!(
+            diagnose(&sources),
+            vec![
+                Diagnostic::Contained { source: source(3, 3, 4), container: source(1, 1, 10) },
+                Diagnostic::Overlap { source: source(4, 25, 40), other: source(2, 20, 30) },
+                Diagnostic::Inverted(source(5, 9, 2)),
+                Diagnostic::Duplicate { source: source(6, 1, 10), first_line: 1 },
+                Diagnostic::Duplicate { source: source(8, 1, 10), first_line: 1 }
+            ]
+        );
+    }
+
+    #[test]
+    fn report_each_containment_once() {
+        let sources = vec![source(1, 5, 6), source(2, 1, 10), source(3, 4, 8)];
+
+        assert_eq!(
+            diagnose(&sources),
+            vec![
+                Diagnostic::Contained { source: source(1, 5, 6), container: source(2, 1, 10) },
+                Diagnostic::Contained { source: source(3, 4, 8), container: source(2, 1, 10) }
+            ]
+        );
+        assert_eq!(
+            diagnose(&sources)[0].to_string(),
+            "line 1: 5-6 is inside 1-10 (line 2)"
+        );
+    }
+
+    #[test]
+    fn touching_ranges_are_fine() {
+        assert_eq!(diagnose(&[source(1, 1, 5), source(2, 6, 10)]), vec![]);
+        assert_eq!(
+            diagnose(&[source(1, 1, 5), source(2, 5, 10)]).len(),
+            1
+        );
+    }
+}
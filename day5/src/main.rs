@@ -1,10 +1,26 @@
+#![allow(clippy::needless_return)]
+
+mod diagnostics;
+mod provenance;
+mod range_index;
+mod range_set;
+#[cfg(test)]
+mod test_support;
+
 use std::cmp::max;
 use std::cmp::min;
 use std::env;
+use std::fmt;
 use std::fs;
+use std::process;
 use std::cmp::Ordering;
+use std::cmp::Reverse;
+
+use provenance::SourceRange;
+use range_index::RangeIndex;
+use range_set::RangeSet;
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug)]
 struct FreshRange(u64, u64);
 
 impl PartialEq for FreshRange {
@@ -17,11 +33,7 @@ impl Eq for FreshRange {}
 
 impl PartialOrd for FreshRange {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        let first = self.0.partial_cmp(&other.0)?;
-        match first {
-            Ordering::Equal => self.1.partial_cmp(&other.1),
-            _ => Some(first)
-        }
+        Some(self.cmp(other))
     }
 }
 
@@ -50,9 +62,10 @@ impl FreshRange {
         input.trim().split('-').map(|x| x.parse::<u64>().unwrap()).collect()
     }
 
-    fn contains(&self, other: &Self) -> bool {
-        self.0 <= other.0 && other.0 <= self.1
-        || self.0 <= other.1 && other.1 <= self.1
+    // Whether the ranges overlap or sit next to each other, so that merging
+    // them covers no IDs that neither of them does.
+    fn touches(&self, other: &Self) -> bool {
+        self.0 <= other.1.saturating_add(1) && other.0 <= self.1.saturating_add(1)
     }
 
     fn merge(&self, other: &Self) -> FreshRange {
@@ -62,8 +75,19 @@ impl FreshRange {
         )
     }
 
-    fn range_len(&self) -> u64 {
-        self.1 - self.0 + 1
+    // A u128, since a range over every u64 holds one ID too many for a u64.
+    // Inverted ranges cover nothing.
+    fn range_len(&self) -> u128 {
+        if self.0 > self.1 {
+            return 0;
+        }
+        (self.1 - self.0) as u128 + 1
+    }
+}
+
+impl fmt::Display for FreshRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}-{}", self.0, self.1)
     }
 }
 
@@ -76,53 +100,33 @@ impl FromIterator<u64> for FreshRange {
     }
 }
 
-fn is_fresh(ranges: &[FreshRange], item: u64) -> bool {
-    if ranges.len() == 0 {
-        return false;
-    }
-
-    if ranges.len() == 1 {
-        return ranges[0].compare_ingrediant(item) == Ordering::Equal;
-    }
+fn is_fresh(ranges: &RangeSet, item: u64) -> bool {
+    ranges
+        .ranges()
+        .binary_search_by(|range| range.compare_ingrediant(item).reverse())
+        .is_ok()
+}
 
-    let mid = ranges.len() / 2; 
-    match ranges[mid].compare_ingrediant(item) {
-        Ordering::Less => is_fresh(&ranges[0..mid], item),
-        Ordering::Greater => is_fresh(&ranges[mid + 1..], item),
-        Ordering::Equal => true
-    }
+fn parse_ranges<'a, T: Iterator<Item = &'a str>>(lines: &mut T) -> RangeSet {
+    return RangeSet::new(source_ranges(&parse_source_ranges(lines)));
 }
 
-fn parse_ranges<'a, T: Iterator<Item = &'a str>>(lines: &mut T) -> Vec<FreshRange> {
-    let mut ranges: Vec<FreshRange> = Vec::new();
+// Reads the range section keeping the line each range is on, counting from 1.
+fn parse_source_ranges<'a, T: Iterator<Item = &'a str>>(lines: &mut T) -> Vec<SourceRange> {
+    let mut sources: Vec<SourceRange> = Vec::new();
 
-    loop {
-        if let Some(line) = lines.next() {
-            if line.trim() == "" {
-                break;
-            }
-            ranges.push(FreshRange::parse(line));
-        } else {
+    for (index, line) in lines.by_ref().enumerate() {
+        if line.trim() == "" {
             break;
         }
-    } 
-
-    if ranges.len() < 2 {
-        return ranges;
+        sources.push(SourceRange { line: index + 1, range: FreshRange::parse(line) });
     }
 
-    ranges.sort();
-    for index in (1..ranges.len()).rev() {
-        let first = &ranges[index - 1];
-        let second = &ranges[index];
+    return sources;
+}
 
-        if first.contains(second) {
-            ranges[index - 1] = first.merge(second);
-            ranges.remove(index);
-        }
-    }
-    
-    return ranges;
+fn source_ranges(sources: &[SourceRange]) -> Vec<FreshRange> {
+    sources.iter().map(|source| source.range).collect()
 }
 
 fn parse_items<'a, T: Iterator<Item = &'a str>>(lines: &mut T) -> Vec<u64> {
@@ -140,25 +144,242 @@ fn parse_items<'a, T: Iterator<Item = &'a str>>(lines: &mut T) -> Vec<u64> {
     } 
 }
 
+// Combines the fresh ranges of the input with those of a second file, or with
+// a bound for the complement.
+#[derive(Debug, PartialEq)]
+enum Operation {
+    Union(String),
+    Intersection(String),
+    Difference(String),
+    Complement(FreshRange)
+}
+
+#[derive(Debug, PartialEq)]
+struct Options {
+    file_name: String,
+    operation: Option<Operation>,
+    queries: Option<String>,
+    gaps: Option<Gaps>,
+    trace: bool,
+    diagnose: bool,
+    updates: Option<String>
+}
+
+// Which spoiled gaps between fresh ranges to list.
+#[derive(Debug, Default, PartialEq)]
+struct Gaps {
+    // Only the parts of gaps within this range.
+    within: Option<FreshRange>,
+    // Only this many of the largest gaps, largest first.
+    largest: Option<usize>
+}
+
+fn parse_args(args: &[String]) -> Options {
+    let mut file_name = String::new();
+    let mut operation = None;
+    let mut queries = None;
+    let mut gaps: Option<Gaps> = None;
+    let mut trace = false;
+    let mut diagnose = false;
+    let mut updates = None;
+
+    let mut args = args.iter().skip(1);
+    while let Some(arg) = args.next() {
+        let mut other_file = || args.next().expect("set operations expect a second range file").clone();
+        match arg.as_str() {
+            "--union" => operation = Some(Operation::Union(other_file())),
+            "--intersection" => operation = Some(Operation::Intersection(other_file())),
+            "--difference" => operation = Some(Operation::Difference(other_file())),
+            "--complement" => {
+                let bound = args.next().and_then(|bound| parse_bound(bound)).expect("--complement expects a range such as 0-1000");
+                operation = Some(Operation::Complement(bound));
+            },
+            "--queries" => queries = Some(args.next().expect("--queries expects a file").clone()),
+            "--trace" => trace = true,
+            "--diagnose" => diagnose = true,
+            "--updates" => updates = Some(args.next().expect("--updates expects a file").clone()),
+            "--gaps" => {
+                gaps.get_or_insert_default();
+            },
+            "--within" => {
+                let bound = args.next().and_then(|bound| parse_bound(bound)).expect("--within expects a range such as 0-1000");
+                gaps.get_or_insert_default().within = Some(bound);
+            },
+            "--largest" => {
+                let count = args.next().and_then(|count| count.parse().ok()).expect("--largest expects a number");
+                gaps.get_or_insert_default().largest = Some(count);
+            },
+            _ => file_name = arg.clone()
+        }
+    }
+
+    return Options { file_name, operation, queries, gaps, trace, diagnose, updates };
+}
+
+fn read_ranges(file_name: &str) -> RangeSet {
+    let file = fs::read_to_string(file_name).unwrap();
+    return parse_ranges(&mut file.lines());
+}
+
+fn run_operation(ranges: &RangeSet, operation: &Operation) {
+    let result = match operation {
+        Operation::Union(other_file) => ranges.union(&read_ranges(other_file)),
+        Operation::Intersection(other_file) => ranges.intersection(&read_ranges(other_file)),
+        Operation::Difference(other_file) => ranges.difference(&read_ranges(other_file)),
+        Operation::Complement(bound) => ranges.complement(bound)
+    };
+
+    for range in result.ranges() {
+        println!("{}", range);
+    }
+    println!("Total IDs: {}", result.id_count());
+}
+
+// Answers one query per line: `fresh <id>`, `count <lower>-<upper>` for the
+// number of fresh IDs in a range, or `nth <n>` for the fresh ID with n fresh
+// IDs below it.
+fn run_queries(ranges: &RangeSet, queries_file: &str) {
+    let queries = fs::read_to_string(queries_file).unwrap();
+
+    for (index, line) in queries.lines().enumerate() {
+        let answer = match line.split_once(' ') {
+            Some(("fresh", item)) => item.trim().parse().ok().map(|item| is_fresh(ranges, item).to_string()),
+            Some(("count", bound)) => parse_bound(bound).map(|bound| ranges.count_within(&bound).to_string()),
+            Some(("nth", n)) => n.trim().parse().ok().map(|n| match ranges.nth(n) {
+                Some(item) => item.to_string(),
+                None => String::from("none")
+            }),
+            _ if line.trim().is_empty() => continue,
+            _ => None
+        };
+
+        match answer {
+            Some(answer) => println!("{}: {}", line, answer),
+            None => {
+                eprintln!("{}:{}: expected fresh <id>, count <lower>-<upper> or nth <n>", queries_file, index + 1);
+                process::exit(1);
+            }
+        }
+    }
+}
+
+fn run_gaps(ranges: &RangeSet, options: &Gaps) {
+    let mut gaps = ranges.gaps();
+    if let Some(bound) = options.within {
+        gaps = gaps.intersection(&RangeSet::new(vec![bound]));
+    }
+
+    let mut listed: Vec<&FreshRange> = gaps.ranges().iter().collect();
+    if let Some(largest) = options.largest {
+        listed.sort_by_key(|gap| Reverse(gap.range_len()));
+        listed.truncate(largest);
+    }
+
+    for gap in listed {
+        println!("{}: {} spoiled", gap, gap.range_len());
+    }
+    println!("Gaps: {}", gaps.ranges().len());
+    println!("Spoiled IDs: {}", gaps.id_count());
+}
+
+// Starts from the input's ranges and applies one line at a time: `add` or
+// `remove` followed by a range, `fresh <id>`, or `list` for the merged ranges.
+// Removing a range takes back one earlier line that added it.
+fn run_updates(sources: &[SourceRange], updates_file: &str) {
+    let mut index = RangeIndex::new();
+    for source in sources {
+        index.insert(source.range);
+    }
+    println!("Total fresh item IDs: {}", index.id_count());
+
+    let updates = fs::read_to_string(updates_file).unwrap();
+    for (index_line, line) in updates.lines().enumerate() {
+        let answer = match line.split_once(' ') {
+            Some(("add", range)) => parse_bound(range).map(|range| {
+                index.insert(range);
+                format!("Total fresh item IDs: {}", index.id_count())
+            }),
+            Some(("remove", range)) => parse_bound(range).map(|range| match index.remove(range) {
+                true => format!("Total fresh item IDs: {}", index.id_count()),
+                false => String::from("not added")
+            }),
+            Some(("fresh", item)) => item.trim().parse().ok().map(|item| index.contains(item).to_string()),
+            None if line.trim() == "list" => {
+                let ranges: Vec<String> = index.ranges().iter().map(|range| range.to_string()).collect();
+                Some(ranges.join(", "))
+            },
+            _ if line.trim().is_empty() => continue,
+            _ => None
+        };
+
+        match answer {
+            Some(answer) => println!("{}: {}", line, answer),
+            None => {
+                eprintln!("{}:{}: expected add <lower>-<upper>, remove <lower>-<upper>, fresh <id> or list", updates_file, index_line + 1);
+                process::exit(1);
+            }
+        }
+    }
+}
+
+fn parse_bound(input: &str) -> Option<FreshRange> {
+    let (lower, upper) = input.trim().split_once('-')?;
+    return Some(FreshRange(lower.parse().ok()?, upper.parse().ok()?));
+}
+
 fn main() {
     let args: Vec<String> = env::args().collect();
-    let file_name = &args[1];
+    let options = parse_args(&args);
 
-    let file = fs::read_to_string(file_name).unwrap();
+    let file = fs::read_to_string(&options.file_name).unwrap();
     let mut lines = file.lines();
 
-    let ranges = parse_ranges(&mut lines);
+    let sources = parse_source_ranges(&mut lines);
+    let ranges = RangeSet::new(source_ranges(&sources));
+    if let Some(updates_file) = &options.updates {
+        run_updates(&sources, updates_file);
+        return;
+    }
+
+    if options.diagnose {
+        let diagnostics = diagnostics::diagnose(&sources);
+        for diagnostic in &diagnostics {
+            println!("{}", diagnostic);
+        }
+        println!("Diagnostics: {}", diagnostics.len());
+        return;
+    }
+
+    if let Some(operation) = &options.operation {
+        run_operation(&ranges, operation);
+        return;
+    }
+
+    if let Some(gaps) = &options.gaps {
+        run_gaps(&ranges, gaps);
+        return;
+    }
+
+    if let Some(queries_file) = &options.queries {
+        run_queries(&ranges, queries_file);
+        return;
+    }
+
     let items = parse_items(&mut lines);
 
+    if options.trace {
+        for item in &items {
+            println!("{}: {}", item, provenance::trace(&sources, *item));
+        }
+    }
+
     let fresh_items = items.iter().filter(|item| {
-        is_fresh(&ranges[..], **item)
-    });
-    let num_fresh_ids = ranges.iter().fold(0 as u64, |acc, range| {
-        acc + range.range_len()
+        is_fresh(&ranges, **item)
     });
+    let num_fresh_ids = ranges.id_count();
 
-    print!("Fresh item count: {}\n", fresh_items.count());
-    print!("Total fresh item IDs: {}\n", num_fresh_ids);
+    println!("Fresh item count: {}", fresh_items.count());
+    println!("Total fresh item IDs: {}", num_fresh_ids);
 }
 
 #[cfg(test)]
@@ -260,11 +481,11 @@ mod tests {
 
     #[test]
     fn find_item() {
-        let ranges = vec![
+        let ranges = RangeSet::new(vec![
             FreshRange(1, 4),
             FreshRange(8, 110),
             FreshRange(200, 550),
-        ];
+        ]);
 
         assert!(is_fresh(&ranges, 8));
         assert!(is_fresh(&ranges, 9));
@@ -288,8 +509,8 @@ mod tests {
         let mut lines = input.lines();
 
         assert_eq!(
-            parse_ranges(&mut lines),
-            vec![
+            parse_ranges(&mut lines).ranges(),
+            &[
                 FreshRange(1, 10),
                 FreshRange(20, 30),
                 FreshRange(50, 200),
@@ -315,11 +536,15 @@ mod tests {
     }
 
     #[test]
-    fn range_contains() {
-        assert!(FreshRange(1, 10).contains(&FreshRange(2, 3)));
-        assert!(FreshRange(3, 10).contains(&FreshRange(2, 3)));
-        assert!(FreshRange(1, 2).contains(&FreshRange(2, 3)));
-        assert!(FreshRange(3, 4).contains(&FreshRange(2, 3)));
+    fn range_touches() {
+        assert!(FreshRange(1, 10).touches(&FreshRange(2, 3)));
+        assert!(FreshRange(3, 10).touches(&FreshRange(2, 3)));
+        assert!(FreshRange(1, 2).touches(&FreshRange(2, 3)));
+        assert!(FreshRange(3, 4).touches(&FreshRange(2, 3)));
+        assert!(FreshRange(2, 3).touches(&FreshRange(1, 10)));
+        assert!(FreshRange(1, 2).touches(&FreshRange(3, 4)));
+        assert!(!FreshRange(1, 2).touches(&FreshRange(4, 5)));
+        assert!(!FreshRange(4, 5).touches(&FreshRange(1, 2)));
     }
 
     #[test]
@@ -342,6 +567,44 @@ mod tests {
         );
     }
 
+    #[test]
+    fn parse_operation_args() {
+        let args = |list: &[&str]| -> Vec<String> { list.iter().map(|arg| arg.to_string()).collect() };
+
+        assert_eq!(
+            parse_args(&args(&["day5", "input"])),
+            Options { file_name: String::from("input"), operation: None, queries: None, gaps: None, trace: false, diagnose: false, updates: None }
+        );
+        assert_eq!(
+            parse_args(&args(&["day5", "input", "--difference", "other"])),
+            Options {
+                file_name: String::from("input"),
+                operation: Some(Operation::Difference(String::from("other"))),
+                queries: None,
+                gaps: None,
+                trace: false,
+                diagnose: false,
+                updates: None
+            }
+        );
+        assert_eq!(
+            parse_args(&args(&["day5", "--complement", "0-100", "input"])),
+            Options {
+                file_name: String::from("input"),
+                operation: Some(Operation::Complement(FreshRange(0, 100))),
+                queries: None,
+                gaps: None,
+                trace: false,
+                diagnose: false,
+                updates: None
+            }
+        );
+        assert_eq!(
+            parse_args(&args(&["day5", "input", "--largest", "3", "--within", "5-50"])).gaps,
+            Some(Gaps { within: Some(FreshRange(5, 50)), largest: Some(3) })
+        );
+    }
+
     #[test]
     fn range_len() {
         assert_eq!(
@@ -353,5 +616,10 @@ mod tests {
             FreshRange(1000, 1010).range_len(),
             11
         );
+
+        assert_eq!(
+            FreshRange(10, 1).range_len(),
+            0
+        );
     }
 }
\ No newline at end of file
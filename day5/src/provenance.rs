@@ -0,0 +1,101 @@
+use std::fmt;
+
+use crate::FreshRange;
+
+// A range as written in the input, with the line it came from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SourceRange {
+    pub line: usize,
+    pub range: FreshRange
+}
+
+// Why an ingredient was classed as fresh or spoiled, in terms of the original
+// range lines rather than the merged ranges.
+#[derive(Debug, PartialEq)]
+pub enum Provenance {
+    // Every line whose range covers the ingredient, in input order.
+    Fresh(Vec<SourceRange>),
+    // The ranges ending closest below and starting closest above the
+    // ingredient, if there are any.
+    Spoiled { below: Option<SourceRange>, above: Option<SourceRange> }
+}
+
+impl fmt::Display for SourceRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (line {})", self.range, self.line)
+    }
+}
+
+impl fmt::Display for Provenance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Provenance::Fresh(sources) => {
+                let sources: Vec<String> = sources.iter().map(|source| source.to_string()).collect();
+                write!(f, "fresh from {}", sources.join(", "))
+            },
+            Provenance::Spoiled { below: Some(below), above: Some(above) } => {
+                write!(f, "spoiled between {} and {}", below, above)
+            },
+            Provenance::Spoiled { below: Some(below), above: None } => write!(f, "spoiled above {}", below),
+            Provenance::Spoiled { below: None, above: Some(above) } => write!(f, "spoiled below {}", above),
+            Provenance::Spoiled { below: None, above: None } => write!(f, "spoiled with no ranges")
+        }
+    }
+}
+
+// Looks through every original range, so ties on the nearest range go to the
+// earliest line.
+pub fn trace(sources: &[SourceRange], item: u64) -> Provenance {
+    let covering: Vec<SourceRange> = sources
+        .iter()
+        .filter(|source| source.range.0 <= item && item <= source.range.1)
+        .copied()
+        .collect();
+    if !covering.is_empty() {
+        return Provenance::Fresh(covering);
+    }
+
+    let mut below: Option<SourceRange> = None;
+    let mut above: Option<SourceRange> = None;
+    for source in sources {
+        if source.range.1 < item && below.is_none_or(|below| source.range.1 > below.range.1) {
+            below = Some(*source);
+        }
+        if source.range.0 > item && above.is_none_or(|above| source.range.0 < above.range.0) {
+            above = Some(*source);
+        }
+    }
+
+    return Provenance::Spoiled { below, above };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::source;
+
+    fn example() -> Vec<SourceRange> {
+        vec![source(1, 3, 5), source(2, 10, 14), source(3, 16, 20), source(4, 12, 18)]
+    }
+
+    #[test]
+    fn trace_fresh_items() {
+        assert_eq!(trace(&example(), 5), Provenance::Fresh(vec![source(1, 3, 5)]));
+        assert_eq!(trace(&example(), 17), Provenance::Fresh(vec![source(3, 16, 20), source(4, 12, 18)]));
+        assert_eq!(
+            trace(&example(), 17).to_string(),
+            "fresh from 16-20 (line 3), 12-18 (line 4)"
+        );
+    }
+
+    #[test]
+    fn trace_spoiled_items() {
+        assert_eq!(
+            trace(&example(), 8),
+            Provenance::Spoiled { below: Some(source(1, 3, 5)), above: Some(source(2, 10, 14)) }
+        );
+        assert_eq!(trace(&example(), 1), Provenance::Spoiled { below: None, above: Some(source(1, 3, 5)) });
+        assert_eq!(trace(&example(), 32).to_string(), "spoiled above 16-20 (line 3)");
+        assert_eq!(trace(&[], 32).to_string(), "spoiled with no ranges");
+    }
+}
@@ -0,0 +1,174 @@
+use std::collections::BTreeMap;
+
+use crate::FreshRange;
+
+// Fresh ranges that can be added and removed while the process runs. Removing
+// a range takes back one earlier addition of it, so IDs that another range
+// covers stay fresh. Lookups and the total take logarithmic time; an update
+// also touches every range merged into the same run of fresh IDs.
+#[derive(Debug, Default)]
+pub struct RangeIndex {
+    // Every range added and not yet removed, with how many times it was added.
+    added: BTreeMap<(u64, u64), usize>,
+    // The merged ranges, from lower end to upper end.
+    merged: BTreeMap<u64, u64>,
+    id_count: u128
+}
+
+impl RangeIndex {
+    pub fn new() -> RangeIndex {
+        RangeIndex::default()
+    }
+
+    // Inverted ranges cover no IDs and are ignored.
+    pub fn insert(&mut self, range: FreshRange) {
+        if range.0 > range.1 {
+            return;
+        }
+
+        *self.added.entry((range.0, range.1)).or_insert(0) += 1;
+        self.merge_in(range);
+    }
+
+    // Takes back one addition of the range, returning false if it wasn't added.
+    pub fn remove(&mut self, range: FreshRange) -> bool {
+        let Some(times) = self.added.get_mut(&(range.0, range.1)) else {
+            return false;
+        };
+        *times -= 1;
+        if *times == 0 {
+            self.added.remove(&(range.0, range.1));
+        }
+
+        // Any other range sharing IDs with this one was merged into the same
+        // run, and starts within it, so the run can be rebuilt from those.
+        let (&lower, &upper) = self.merged.range(..=range.0).next_back().unwrap();
+        self.take_merged(lower);
+
+        let inside: Vec<FreshRange> = self.added
+            .range((lower, 0)..=(upper, u64::MAX))
+            .map(|(&(lower, upper), _)| FreshRange(lower, upper))
+            .collect();
+        for range in inside {
+            self.merge_in(range);
+        }
+
+        return true;
+    }
+
+    pub fn contains(&self, item: u64) -> bool {
+        match self.merged.range(..=item).next_back() {
+            Some((_, &upper)) => item <= upper,
+            None => false
+        }
+    }
+
+    pub fn id_count(&self) -> u128 {
+        self.id_count
+    }
+
+    pub fn ranges(&self) -> Vec<FreshRange> {
+        self.merged.iter().map(|(&lower, &upper)| FreshRange(lower, upper)).collect()
+    }
+
+    // Merges a range with every merged range it touches.
+    fn merge_in(&mut self, range: FreshRange) {
+        let mut merged = range;
+
+        if let Some((&lower, &upper)) = self.merged.range(..range.0).next_back()
+            && FreshRange(lower, upper).touches(&range) {
+            merged = merged.merge(&self.take_merged(lower));
+        }
+
+        loop {
+            let next = self.merged.range(range.0..).next();
+            match next {
+                Some((&lower, &upper)) if FreshRange(lower, upper).touches(&merged) => {
+                    merged = merged.merge(&self.take_merged(lower));
+                },
+                _ => break
+            }
+        }
+
+        self.id_count += merged.range_len();
+        self.merged.insert(merged.0, merged.1);
+    }
+
+    fn take_merged(&mut self, lower: u64) -> FreshRange {
+        let upper = self.merged.remove(&lower).unwrap();
+        let range = FreshRange(lower, upper);
+        self.id_count -= range.range_len();
+        return range;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RangeSet;
+    use crate::test_support::Random;
+
+    #[test]
+    fn insert_and_remove() {
+        let mut index = RangeIndex::new();
+        index.insert(FreshRange(1, 10));
+        index.insert(FreshRange(3, 5));
+        index.insert(FreshRange(11, 12));
+        index.insert(FreshRange(20, 25));
+
+        assert_eq!(index.ranges(), vec![FreshRange(1, 12), FreshRange(20, 25)]);
+        assert_eq!(index.id_count(), 18);
+
+        assert!(index.remove(FreshRange(1, 10)));
+        assert_eq!(index.ranges(), vec![FreshRange(3, 5), FreshRange(11, 12), FreshRange(20, 25)]);
+        assert_eq!(index.id_count(), 11);
+        assert!(index.contains(4));
+        assert!(!index.contains(7));
+
+        assert!(!index.remove(FreshRange(1, 10)));
+        assert!(!index.remove(FreshRange(20, 24)));
+        assert!(index.remove(FreshRange(20, 25)));
+        assert!(!index.contains(20));
+        assert_eq!(index.id_count(), 5);
+    }
+
+    #[test]
+    fn repeated_ranges() {
+        let mut index = RangeIndex::new();
+        index.insert(FreshRange(5, u64::MAX));
+        index.insert(FreshRange(5, u64::MAX));
+        index.insert(FreshRange(9, 2));
+
+        assert!(index.remove(FreshRange(5, u64::MAX)));
+        assert!(index.contains(u64::MAX));
+        assert_eq!(index.id_count(), (u64::MAX - 4) as u128);
+        assert!(index.remove(FreshRange(5, u64::MAX)));
+        assert_eq!(index.ranges(), vec![]);
+        assert_eq!(index.id_count(), 0);
+    }
+
+    #[test]
+    fn matches_rebuilding() {
+        let mut random = Random(50);
+        let mut index = RangeIndex::new();
+        let mut added: Vec<FreshRange> = Vec::new();
+
+        for _ in 0..2000 {
+            if !added.is_empty() && random.below(3) == 0 {
+                let removed = added.swap_remove(random.below(added.len() as u64) as usize);
+                assert!(index.remove(removed));
+            } else {
+                let lower = random.below(200);
+                let range = FreshRange(lower, lower + random.below(20));
+                added.push(range);
+                index.insert(range);
+            }
+
+            let expected = RangeSet::new(added.clone());
+            assert_eq!(index.ranges(), expected.ranges());
+            assert_eq!(index.id_count(), expected.id_count());
+            let item = random.below(230);
+            assert_eq!(index.contains(item), crate::is_fresh(&expected, item));
+        }
+    }
+}
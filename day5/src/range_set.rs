@@ -0,0 +1,319 @@
+use std::cmp::max;
+use std::cmp::min;
+
+use crate::FreshRange;
+
+// Fresh ranges kept sorted, with every overlapping or touching pair merged,
+// so no ID is covered twice and lookups can binary search.
+#[derive(Debug, Default, PartialEq)]
+pub struct RangeSet {
+    ranges: Vec<FreshRange>,
+    // How many IDs the ranges before each one cover, for counting queries.
+    before: Vec<u128>
+}
+
+impl RangeSet {
+    // Inverted ranges cover no IDs and are dropped.
+    pub fn new(mut ranges: Vec<FreshRange>) -> RangeSet {
+        ranges.retain(|range| range.0 <= range.1);
+        ranges.sort();
+
+        let mut merged: Vec<FreshRange> = Vec::with_capacity(ranges.len());
+        for range in ranges {
+            match merged.last_mut() {
+                Some(last) if last.touches(&range) => *last = last.merge(&range),
+                _ => merged.push(range)
+            }
+        }
+
+        return RangeSet::from_normalised(merged);
+    }
+
+    fn from_normalised(ranges: Vec<FreshRange>) -> RangeSet {
+        let mut before = Vec::with_capacity(ranges.len());
+        let mut count = 0;
+        for range in &ranges {
+            before.push(count);
+            count += range.range_len();
+        }
+
+        return RangeSet { ranges, before };
+    }
+
+    pub fn ranges(&self) -> &[FreshRange] {
+        &self.ranges
+    }
+
+    // The number of IDs covered by any range.
+    pub fn id_count(&self) -> u128 {
+        match self.ranges.last() {
+            Some(last) => self.before[self.before.len() - 1] + last.range_len(),
+            None => 0
+        }
+    }
+
+    // The number of fresh IDs no greater than the given one.
+    fn rank(&self, item: u64) -> u128 {
+        let index = self.ranges.partition_point(|range| range.0 <= item);
+        if index == 0 {
+            return 0;
+        }
+
+        let range = &self.ranges[index - 1];
+        return self.before[index - 1] + FreshRange(range.0, min(item, range.1)).range_len();
+    }
+
+    // The number of fresh IDs within the bound. An inverted bound holds none.
+    pub fn count_within(&self, bound: &FreshRange) -> u128 {
+        if bound.0 > bound.1 {
+            return 0;
+        }
+
+        let below = match bound.0.checked_sub(1) {
+            Some(last_below) => self.rank(last_below),
+            None => 0
+        };
+        return self.rank(bound.1) - below;
+    }
+
+    // The fresh ID with n fresh IDs below it, so the lowest one is the 0th as
+    // with Iterator::nth.
+    pub fn nth(&self, n: u128) -> Option<u64> {
+        let index = self.before.partition_point(|before| *before <= n).checked_sub(1)?;
+        let offset = n - self.before[index];
+        if offset >= self.ranges[index].range_len() {
+            return None;
+        }
+
+        return Some(self.ranges[index].0 + offset as u64);
+    }
+
+    pub fn union(&self, other: &RangeSet) -> RangeSet {
+        RangeSet::new(self.ranges.iter().chain(&other.ranges).copied().collect())
+    }
+
+    pub fn intersection(&self, other: &RangeSet) -> RangeSet {
+        let mut ranges = Vec::new();
+        let (mut mine, mut theirs) = (0, 0);
+
+        // Both lists are sorted and disjoint, so whichever range ends first
+        // can't overlap anything further along the other list.
+        while mine < self.ranges.len() && theirs < other.ranges.len() {
+            let (first, second) = (&self.ranges[mine], &other.ranges[theirs]);
+            let lower = max(first.0, second.0);
+            let upper = min(first.1, second.1);
+            if lower <= upper {
+                ranges.push(FreshRange(lower, upper));
+            }
+
+            if first.1 < second.1 {
+                mine += 1;
+            } else {
+                theirs += 1;
+            }
+        }
+
+        return RangeSet::new(ranges);
+    }
+
+    pub fn difference(&self, other: &RangeSet) -> RangeSet {
+        self.intersection(&other.complement(&FreshRange(0, u64::MAX)))
+    }
+
+    // The spoiled spans between the first fresh ID and the last.
+    pub fn gaps(&self) -> RangeSet {
+        match (self.ranges.first(), self.ranges.last()) {
+            (Some(first), Some(last)) => self.complement(&FreshRange(first.0, last.1)),
+            _ => RangeSet::default()
+        }
+    }
+
+    // The IDs within the bound that no range covers.
+    pub fn complement(&self, bound: &FreshRange) -> RangeSet {
+        let mut gaps = Vec::new();
+        // The first ID not yet known to be covered, or None past the last ID.
+        let mut next = Some(bound.0);
+
+        for range in &self.ranges {
+            if range.1 < bound.0 {
+                continue;
+            }
+            if range.0 > bound.1 {
+                break;
+            }
+
+            if let Some(start) = next && start < range.0 {
+                gaps.push(FreshRange(start, range.0 - 1));
+            }
+            next = range.1.checked_add(1);
+        }
+
+        if let Some(start) = next && start <= bound.1 {
+            gaps.push(FreshRange(start, bound.1));
+        }
+
+        return RangeSet::from_normalised(gaps);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::is_fresh;
+    use crate::test_support::Random;
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn merge_nested_ranges() {
+        let set = RangeSet::new(vec![FreshRange(1, 10), FreshRange(2, 3), FreshRange(5, 6)]);
+
+        assert_eq!(set.ranges(), &[FreshRange(1, 10)]);
+        assert_eq!(set.id_count(), 10);
+
+        let set = RangeSet::new(vec![FreshRange(1, 2), FreshRange(9, 4), FreshRange(5, 6)]);
+        assert_eq!(set.ranges(), &[FreshRange(1, 2), FreshRange(5, 6)]);
+    }
+
+    #[test]
+    fn merge_touching_ranges() {
+        let set = RangeSet::new(vec![FreshRange(5, 6), FreshRange(1, 2), FreshRange(3, 4), FreshRange(8, 9)]);
+
+        assert_eq!(set.ranges(), &[FreshRange(1, 6), FreshRange(8, 9)]);
+        assert!(is_fresh(&set, 6));
+        assert!(!is_fresh(&set, 7));
+        assert!(!is_fresh(&set, 0));
+        assert!(!is_fresh(&set, 10));
+    }
+
+    #[test]
+    fn range_reaching_the_last_id() {
+        let set = RangeSet::new(vec![FreshRange(u64::MAX - 1, u64::MAX), FreshRange(5, u64::MAX - 2)]);
+
+        assert_eq!(set.ranges(), &[FreshRange(5, u64::MAX)]);
+        assert!(is_fresh(&set, u64::MAX));
+    }
+
+    fn random_set(random: &mut Random) -> (RangeSet, BTreeSet<u64>) {
+        let mut ranges = Vec::new();
+        let mut naive = BTreeSet::new();
+        for _ in 0..random.below(8) {
+            let lower = random.below(100);
+            let upper = lower + random.below(15);
+            ranges.push(FreshRange(lower, upper));
+            naive.extend(lower..=upper);
+        }
+
+        return (RangeSet::new(ranges), naive);
+    }
+
+    fn ids(set: &RangeSet) -> BTreeSet<u64> {
+        set.ranges().iter().flat_map(|range| range.0..=range.1).collect()
+    }
+
+    #[test]
+    fn set_algebra() {
+        let first = RangeSet::new(vec![FreshRange(1, 5), FreshRange(10, 20)]);
+        let second = RangeSet::new(vec![FreshRange(4, 12), FreshRange(18, 30)]);
+
+        assert_eq!(first.union(&second).ranges(), &[FreshRange(1, 30)]);
+        assert_eq!(
+            first.intersection(&second).ranges(),
+            &[FreshRange(4, 5), FreshRange(10, 12), FreshRange(18, 20)]
+        );
+        assert_eq!(first.difference(&second).ranges(), &[FreshRange(1, 3), FreshRange(13, 17)]);
+        assert_eq!(
+            first.complement(&FreshRange(0, 25)).ranges(),
+            &[FreshRange(0, 0), FreshRange(6, 9), FreshRange(21, 25)]
+        );
+        assert_eq!(first.complement(&FreshRange(11, 19)).ranges(), &[]);
+        assert_eq!(RangeSet::default().complement(&FreshRange(0, u64::MAX)).id_count(), 1 << 64);
+        assert_eq!(RangeSet::new(vec![FreshRange(0, u64::MAX)]).complement(&FreshRange(0, u64::MAX)).ranges(), &[]);
+    }
+
+    #[test]
+    fn algebra_matches_naive_sets() {
+        let mut random = Random(7);
+        let bound = FreshRange(10, 90);
+
+        for _ in 0..500 {
+            let (first, first_ids) = random_set(&mut random);
+            let (second, second_ids) = random_set(&mut random);
+
+            assert_eq!(ids(&first.union(&second)), &first_ids | &second_ids);
+            assert_eq!(ids(&first.intersection(&second)), &first_ids & &second_ids);
+            assert_eq!(ids(&first.difference(&second)), &first_ids - &second_ids);
+            assert_eq!(
+                ids(&first.complement(&bound)),
+                (bound.0..=bound.1).filter(|id| !first_ids.contains(id)).collect()
+            );
+        }
+    }
+
+    #[test]
+    fn find_gaps() {
+        let set = RangeSet::new(vec![FreshRange(3, 5), FreshRange(10, 14), FreshRange(16, 20), FreshRange(30, 30)]);
+
+        assert_eq!(set.gaps().ranges(), &[FreshRange(6, 9), FreshRange(15, 15), FreshRange(21, 29)]);
+        assert_eq!(RangeSet::new(vec![FreshRange(1, 2)]).gaps(), RangeSet::default());
+        assert_eq!(RangeSet::default().gaps(), RangeSet::default());
+    }
+
+    #[test]
+    fn count_queries() {
+        let set = RangeSet::new(vec![FreshRange(3, 5), FreshRange(10, 14), FreshRange(16, 20), FreshRange(12, 18)]);
+
+        assert_eq!(set.count_within(&FreshRange(0, 100)), 14);
+        assert_eq!(set.count_within(&FreshRange(4, 11)), 4);
+        assert_eq!(set.count_within(&FreshRange(6, 9)), 0);
+        assert_eq!(set.count_within(&FreshRange(20, 20)), 1);
+        assert_eq!(set.count_within(&FreshRange(20, 10)), 0);
+        assert_eq!(set.count_within(&FreshRange(u64::MAX, 0)), 0);
+        assert_eq!(set.nth(0), Some(3));
+        assert_eq!(set.nth(3), Some(10));
+        assert_eq!(set.nth(13), Some(20));
+        assert_eq!(set.nth(14), None);
+        assert_eq!(RangeSet::default().nth(0), None);
+
+        let everything = RangeSet::new(vec![FreshRange(0, u64::MAX)]);
+        assert_eq!(everything.count_within(&FreshRange(0, u64::MAX)), 1 << 64);
+        assert_eq!(everything.nth(u64::MAX as u128), Some(u64::MAX));
+        assert_eq!(everything.nth(1 << 64), None);
+    }
+
+    #[test]
+    fn queries_match_naive_set() {
+        let mut random = Random(11);
+
+        for _ in 0..300 {
+            let (set, naive) = random_set(&mut random);
+            let sorted: Vec<u64> = naive.iter().copied().collect();
+
+            for n in 0..=sorted.len() {
+                assert_eq!(set.nth(n as u128), sorted.get(n).copied());
+            }
+            for _ in 0..20 {
+                let lower = random.below(120);
+                let upper = lower + random.below(40);
+                let expected = naive.range(lower..=upper).count() as u128;
+                assert_eq!(set.count_within(&FreshRange(lower, upper)), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn matches_naive_set() {
+        let mut random = Random(2025);
+
+        for _ in 0..500 {
+            let (set, naive) = random_set(&mut random);
+
+            assert_eq!(set.id_count(), naive.len() as u128);
+            for item in 0..120 {
+                assert_eq!(is_fresh(&set, item), naive.contains(&item), "item {} in {:?}", item, set);
+            }
+            for pair in set.ranges().windows(2) {
+                assert!(pair[0].1 + 1 < pair[1].0, "{:?} should have been merged", pair);
+            }
+        }
+    }
+}
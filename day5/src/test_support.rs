@@ -0,0 +1,20 @@
+// Helpers shared by the tests of several modules.
+
+use crate::FreshRange;
+use crate::provenance::SourceRange;
+
+// A small xorshift generator, so the property tests need no dependencies.
+pub struct Random(pub u64);
+
+impl Random {
+    pub fn below(&mut self, bound: u64) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        return self.0 % bound;
+    }
+}
+
+pub fn source(line: usize, lower: u64, upper: u64) -> SourceRange {
+    SourceRange { line, range: FreshRange(lower, upper) }
+}
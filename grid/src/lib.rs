@@ -0,0 +1,386 @@
+use std::fmt;
+use std::ops::Index;
+use std::ops::IndexMut;
+
+const VON_NEUMANN: [(isize, isize); 4] = [
+    (-1, 0),
+    (0, -1),
+    (0, 1),
+    (1, 0)
+];
+
+const MOORE: [(isize, isize); 8] = [
+    (-1, -1),
+    (-1, 0),
+    (-1, 1),
+    (0, -1),
+    (0, 1),
+    (1, -1),
+    (1, 0),
+    (1, 1)
+];
+
+/// Which positions count as neighbours, as `(row, column)` offsets from a cell.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Neighbourhood {
+    /// The 4 orthogonally adjacent cells.
+    VonNeumann,
+    /// The 8 orthogonally or diagonally adjacent cells.
+    Moore,
+    /// Every other cell within the given number of steps in any direction.
+    Radius(usize),
+    Custom(Vec<(isize, isize)>)
+}
+
+#[derive(Debug, PartialEq)]
+pub enum MaskError {
+    Ragged { row: usize, expected: usize, found: usize },
+    NoCentre { rows: usize, columns: usize }
+}
+
+impl fmt::Display for MaskError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MaskError::Ragged { row, expected, found } => {
+                write!(f, "mask row {} has {} cells but {} were expected", row, found, expected)
+            },
+            MaskError::NoCentre { rows, columns } => {
+                write!(f, "a {}x{} mask has no centre cell", rows, columns)
+            }
+        }
+    }
+}
+
+impl Neighbourhood {
+    pub fn offsets(&self) -> Vec<(isize, isize)> {
+        match self {
+            Neighbourhood::VonNeumann => VON_NEUMANN.to_vec(),
+            Neighbourhood::Moore => MOORE.to_vec(),
+            Neighbourhood::Radius(radius) => {
+                let radius = *radius as isize;
+                let mut offsets = Vec::new();
+                for row in -radius..=radius {
+                    for column in -radius..=radius {
+                        if (row, column) != (0, 0) {
+                            offsets.push((row, column));
+                        }
+                    }
+                }
+                offsets
+            },
+            Neighbourhood::Custom(offsets) => offsets.clone()
+        }
+    }
+
+    /// Reads a custom neighbourhood drawn as a grid with odd sides, where `#`
+    /// marks a neighbour relative to the centre cell. The centre itself is
+    /// never a neighbour.
+    ///
+    /// ```text
+    /// .#.
+    /// #.#
+    /// .#.
+    /// ```
+    pub fn from_mask(mask: &str) -> Result<Neighbourhood, MaskError> {
+        let lines: Vec<&str> = mask.lines().map(|line| line.trim()).filter(|line| !line.is_empty()).collect();
+        let rows = lines.len();
+        let columns = lines.first().map_or(0, |line| line.chars().count());
+
+        for (row, line) in lines.iter().enumerate() {
+            let found = line.chars().count();
+            if found != columns {
+                return Err(MaskError::Ragged { row: row + 1, expected: columns, found });
+            }
+        }
+
+        if rows.is_multiple_of(2) || columns.is_multiple_of(2) {
+            return Err(MaskError::NoCentre { rows, columns });
+        }
+
+        let (centre_row, centre_column) = ((rows / 2) as isize, (columns / 2) as isize);
+        let mut offsets = Vec::new();
+        for (row, line) in lines.iter().enumerate() {
+            for (column, cell) in line.chars().enumerate() {
+                let offset = (row as isize - centre_row, column as isize - centre_column);
+                if cell == '#' && offset != (0, 0) {
+                    offsets.push(offset);
+                }
+            }
+        }
+
+        Ok(Neighbourhood::Custom(offsets))
+    }
+}
+
+/// A rectangular grid stored row by row and addressed by `(row, column)`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Grid<T> {
+    cells: Vec<T>,
+    rows: usize,
+    columns: usize
+}
+
+impl<T> Grid<T> {
+    /// Builds a grid from cells listed row by row.
+    ///
+    /// Panics if the number of cells is not `rows * columns`.
+    pub fn new(rows: usize, columns: usize, cells: Vec<T>) -> Grid<T> {
+        assert_eq!(cells.len(), rows * columns, "grid of {}x{} needs {} cells", rows, columns, rows * columns);
+        Grid { cells, rows, columns }
+    }
+
+    pub fn filled(rows: usize, columns: usize, value: T) -> Grid<T> where T: Clone {
+        Grid::new(rows, columns, vec![value; rows * columns])
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    pub fn contains(&self, row: usize, column: usize) -> bool {
+        row < self.rows && column < self.columns
+    }
+
+    pub fn get(&self, row: usize, column: usize) -> Option<&T> {
+        if !self.contains(row, column) {
+            return None;
+        }
+        self.cells.get(row * self.columns + column)
+    }
+
+    pub fn get_mut(&mut self, row: usize, column: usize) -> Option<&mut T> {
+        if !self.contains(row, column) {
+            return None;
+        }
+        self.cells.get_mut(row * self.columns + column)
+    }
+
+    /// The cells in row order.
+    pub fn cells(&self) -> &[T] {
+        &self.cells
+    }
+
+    /// Every position in row order.
+    pub fn positions(&self) -> impl Iterator<Item = (usize, usize)> {
+        let columns = self.columns;
+        (0..self.cells.len()).map(move |index| (index / columns, index % columns))
+    }
+
+    /// Every position in row order alongside its cell.
+    pub fn iter(&self) -> impl Iterator<Item = ((usize, usize), &T)> {
+        self.positions().zip(self.cells.iter())
+    }
+
+    /// The up to 4 orthogonally adjacent positions that lie on the grid.
+    pub fn neighbours4(&self, row: usize, column: usize) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.neighbours(row, column, &VON_NEUMANN)
+    }
+
+    /// The up to 8 orthogonally or diagonally adjacent positions that lie on
+    /// the grid.
+    pub fn neighbours8(&self, row: usize, column: usize) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.neighbours(row, column, &MOORE)
+    }
+
+    /// The positions at the given offsets that lie on the grid.
+    pub fn neighbours<'a>(
+        &'a self,
+        row: usize,
+        column: usize,
+        offsets: &'a [(isize, isize)]
+    ) -> impl Iterator<Item = (usize, usize)> + 'a {
+        offsets.iter().filter_map(move |(row_offset, column_offset)| {
+            let row = row.checked_add_signed(*row_offset)?;
+            let column = column.checked_add_signed(*column_offset)?;
+            if self.contains(row, column) {
+                Some((row, column))
+            } else {
+                None
+            }
+        })
+    }
+
+    /// The positions at the given offsets, wrapping around the edges as if
+    /// the grid were a torus. On grids narrower than the offsets reach, each
+    /// position is only given once and the cell itself is left out.
+    pub fn wrapping_neighbours(
+        &self,
+        row: usize,
+        column: usize,
+        offsets: &[(isize, isize)]
+    ) -> impl Iterator<Item = (usize, usize)> {
+        let (rows, columns) = (self.rows as isize, self.columns as isize);
+        let mut neighbours: Vec<(usize, usize)> = Vec::with_capacity(offsets.len());
+
+        for (row_offset, column_offset) in offsets {
+            let position = (
+                (row as isize + row_offset).rem_euclid(rows) as usize,
+                (column as isize + column_offset).rem_euclid(columns) as usize
+            );
+            if position != (row, column) && !neighbours.contains(&position) {
+                neighbours.push(position);
+            }
+        }
+
+        neighbours.into_iter()
+    }
+}
+
+impl<T> Index<(usize, usize)> for Grid<T> {
+    type Output = T;
+
+    fn index(&self, (row, column): (usize, usize)) -> &T {
+        self.get(row, column)
+            .unwrap_or_else(|| panic!("({}, {}) is outside a {}x{} grid", row, column, self.rows, self.columns))
+    }
+}
+
+impl<T> IndexMut<(usize, usize)> for Grid<T> {
+    fn index_mut(&mut self, (row, column): (usize, usize)) -> &mut T {
+        let (rows, columns) = (self.rows, self.columns);
+        self.get_mut(row, column)
+            .unwrap_or_else(|| panic!("({}, {}) is outside a {}x{} grid", row, column, rows, columns))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn numbered(rows: usize, columns: usize) -> Grid<usize> {
+        Grid::new(rows, columns, (0..rows * columns).collect())
+    }
+
+    #[test]
+    fn addressing() {
+        let grid = numbered(3, 4);
+
+        assert_eq!(grid.rows(), 3);
+        assert_eq!(grid.columns(), 4);
+        assert_eq!(grid[(0, 0)], 0);
+        assert_eq!(grid[(1, 2)], 6);
+        assert_eq!(grid[(2, 3)], 11);
+
+        assert_eq!(grid.get(2, 3), Some(&11));
+        assert_eq!(grid.get(3, 0), None);
+        assert_eq!(grid.get(0, 4), None);
+    }
+
+    #[test]
+    fn mutation() {
+        let mut grid = Grid::filled(2, 2, '.');
+        grid[(1, 0)] = '@';
+        *grid.get_mut(0, 1).unwrap() = '@';
+
+        assert_eq!(grid.cells(), &['.', '@', '@', '.']);
+        assert_eq!(grid.get_mut(2, 0), None);
+    }
+
+    #[test]
+    #[should_panic]
+    fn index_out_of_bounds() {
+        let grid = numbered(2, 2);
+        let _ = grid[(0, 2)];
+    }
+
+    #[test]
+    fn positions() {
+        let grid = numbered(2, 3);
+
+        assert_eq!(
+            grid.positions().collect::<Vec<_>>(),
+            vec![(0, 0), (0, 1), (0, 2), (1, 0), (1, 1), (1, 2)]
+        );
+        assert_eq!(grid.iter().nth(4), Some(((1, 1), &4)));
+    }
+
+    #[test]
+    fn neighbours() {
+        let grid = numbered(3, 3);
+
+        assert_eq!(
+            grid.neighbours4(1, 1).collect::<Vec<_>>(),
+            vec![(0, 1), (1, 0), (1, 2), (2, 1)]
+        );
+        assert_eq!(grid.neighbours8(1, 1).count(), 8);
+
+        assert_eq!(
+            grid.neighbours4(0, 0).collect::<Vec<_>>(),
+            vec![(0, 1), (1, 0)]
+        );
+        assert_eq!(
+            grid.neighbours8(0, 0).collect::<Vec<_>>(),
+            vec![(0, 1), (1, 0), (1, 1)]
+        );
+        assert_eq!(
+            grid.neighbours8(2, 2).collect::<Vec<_>>(),
+            vec![(1, 1), (1, 2), (2, 1)]
+        );
+        assert_eq!(grid.neighbours8(0, 1).count(), 5);
+    }
+
+    #[test]
+    fn neighbourhood_offsets() {
+        assert_eq!(Neighbourhood::VonNeumann.offsets().len(), 4);
+        assert_eq!(Neighbourhood::Moore.offsets().len(), 8);
+        assert_eq!(Neighbourhood::Radius(1).offsets(), Neighbourhood::Moore.offsets());
+        assert_eq!(Neighbourhood::Radius(2).offsets().len(), 24);
+        assert_eq!(Neighbourhood::Radius(0).offsets(), vec![]);
+
+        let grid = numbered(5, 5);
+        let offsets = Neighbourhood::Radius(2).offsets();
+        assert_eq!(grid.neighbours(0, 0, &offsets).count(), 8);
+        assert_eq!(grid.neighbours(2, 2, &offsets).count(), 24);
+    }
+
+    #[test]
+    fn neighbourhood_from_mask() {
+        assert_eq!(
+            Neighbourhood::from_mask(".#.\n#.#\n.#.\n"),
+            Ok(Neighbourhood::Custom(vec![(-1, 0), (0, -1), (0, 1), (1, 0)]))
+        );
+        assert_eq!(
+            Neighbourhood::from_mask("#####"),
+            Ok(Neighbourhood::Custom(vec![(0, -2), (0, -1), (0, 1), (0, 2)]))
+        );
+        assert_eq!(
+            Neighbourhood::from_mask("###\n##\n###"),
+            Err(MaskError::Ragged { row: 2, expected: 3, found: 2 })
+        );
+        assert_eq!(
+            Neighbourhood::from_mask("##\n##"),
+            Err(MaskError::NoCentre { rows: 2, columns: 2 })
+        );
+    }
+
+    #[test]
+    fn wrapping_neighbours() {
+        let grid = numbered(3, 4);
+
+        assert_eq!(
+            grid.wrapping_neighbours(0, 0, &VON_NEUMANN).collect::<Vec<_>>(),
+            vec![(2, 0), (0, 3), (0, 1), (1, 0)]
+        );
+        assert_eq!(grid.wrapping_neighbours(2, 3, &MOORE).count(), 8);
+        assert!(grid.wrapping_neighbours(2, 3, &MOORE).all(|(row, column)| grid.contains(row, column)));
+
+        let line = numbered(1, 2);
+        assert_eq!(
+            line.wrapping_neighbours(0, 0, &VON_NEUMANN).collect::<Vec<_>>(),
+            vec![(0, 1)]
+        );
+        assert_eq!(Grid::filled(1, 1, 0).wrapping_neighbours(0, 0, &MOORE).count(), 0);
+    }
+}